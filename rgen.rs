@@ -1,51 +1,264 @@
+extern crate serialize;
+extern crate time;
+
+use serialize::json;
+
 use std::io::{BufferedReader, BufferedWriter, File, fs};
 use std::io;
 use std::os;
 use std::str;
 
+//Options set by command line flags or the [build] table of rgen.toml.
+struct BuildOptions {
+	drafts: bool, //--drafts: build pages with draft: true
+	future: bool //--future: build pages whose publishDate is in the future or whose expiryDate has passed
+}
+
+//Warnings given during the build, for the build report.
+local_data_key!(WARNINGS: Vec<~str>)
+
+//Print a warning and remember it for the build report.
+fn warn(message: ~str) {
+	println!("Warning: {}", message);
+	let mut warnings = match WARNINGS.replace(None) {
+		Some(warnings) => warnings,
+		None => Vec::new()
+	};
+	warnings.push(message);
+	WARNINGS.replace(Some(warnings));
+}
+
+//Errors in site files. The build goes on so that all of them are found, but the output isn't replaced.
+local_data_key!(ERRORS: Vec<~str>)
+
+//Print an error in a site file and remember it so that the build fails.
+fn reportError(message: ~str) {
+	println!("Error: {}", message);
+	let mut errors = match ERRORS.replace(None) {
+		Some(errors) => errors,
+		None => Vec::new()
+	};
+	errors.push(message);
+	ERRORS.replace(Some(errors));
+}
+
+static USAGE: &'static str = "Usage: ./rgen <path to site files> [--drafts|--no-drafts] [--future|--no-future] [--output <dir>] [--base-url <url>] [--env <environment>] [--clean] [--report json [--report-file <path>]]\n       ./rgen new site <path>\n       ./rgen new page <path in content> [--kind <archetype>] [--site <path to site files>]";
+
 fn main() {
+	//Subcommands
+	if os::args().len() >= 2 && os::args().get(1).as_slice() == "new" {
+		if os::args().len() == 4 && os::args().get(2).as_slice() == "site" {
+			newSite(&Path::new(os::args().get(3).to_owned()));
+		}
+		else if os::args().len() >= 4 && os::args().get(2).as_slice() == "page" {
+			let mut kind: Option<~str> = None;
+			let mut site = ".".to_owned();
+			let mut i = 4;
+			while i < os::args().len() {
+				if i + 1 == os::args().len() {
+					println!("Error: {} needs a value. {}", os::args().get(i), USAGE);
+					os::set_exit_status(1);
+					return;
+				}
+				match os::args().get(i).as_slice() {
+					"--kind" => { kind = Some(os::args().get(i + 1).to_owned()) },
+					"--site" => { site = os::args().get(i + 1).to_owned() },
+					_ => {
+						println!("Error: Unknown option {}. {}", os::args().get(i), USAGE);
+						os::set_exit_status(1);
+						return;
+					}
+				}
+				i += 2;
+			}
+			newPage(&Path::new(site), os::args().get(3).as_slice(), kind);
+		}
+		else {
+			println!("{}", USAGE);
+			os::set_exit_status(1);
+		}
+		return;
+	}
+	//Command line flags. Anything not given here comes from rgen.toml.
+	let mut draftsFlag: Option<bool> = None;
+	let mut futureFlag: Option<bool> = None;
+	let mut outputFlag: Option<~str> = None;
+	let mut clean = false;
+	let mut baseURLFlag: Option<~str> = None;
+	let mut envFlag: Option<~str> = None;
+	let mut report = false;
+	let mut reportFileFlag: Option<~str> = None;
+	let mut sitePath = "".to_owned();
+	let args = os::args();
+	let mut i = 1;
+	while i < args.len() {
+		let arg = args.get(i).to_owned();
+		match arg.as_slice() {
+			"--drafts" => { draftsFlag = Some(true) },
+			"--no-drafts" => { draftsFlag = Some(false) },
+			"--future" => { futureFlag = Some(true) },
+			"--no-future" => { futureFlag = Some(false) },
+			"--clean" => { clean = true },
+			"--report" => {
+				if i + 1 >= args.len() || args.get(i + 1).as_slice() != "json" {
+					println!("Error: --report needs a format. The only format is json. {}", USAGE);
+					os::set_exit_status(1);
+					return;
+				}
+				i += 1;
+				report = true;
+			},
+			"--output" | "--base-url" | "--env" | "--report-file" => {
+				if i + 1 >= args.len() {
+					println!("Error: {} needs a value. {}", arg, USAGE);
+					os::set_exit_status(1);
+					return;
+				}
+				i += 1;
+				if arg == "--output".to_owned() {
+					outputFlag = Some(args.get(i).to_owned());
+				}
+				else if arg == "--env".to_owned() {
+					envFlag = Some(args.get(i).to_owned());
+				}
+				else if arg == "--report-file".to_owned() {
+					reportFileFlag = Some(args.get(i).to_owned());
+				}
+				else {
+					baseURLFlag = Some(args.get(i).to_owned());
+				}
+			},
+			_ => {
+				if arg.starts_with("--") {
+					println!("Error: Unknown option {}. {}", arg, USAGE);
+					os::set_exit_status(1);
+					return;
+				}
+				sitePath = arg.to_owned();
+			}
+		}
+		i += 1;
+	}
 	//Ensure that the user gave the correct command line argument. 
-	if !(os::args().len() >= 2) {
-		println!("Usage: ./rgen <path to site files>");
+	if sitePath == "".to_owned() {
+		println!("{}", USAGE);
+		os::set_exit_status(1);
 		return;
 	}
-	let path = Path::new(os::args().get(1).to_owned());
+	let path = Path::new(sitePath);
+	//Where --report json writes the report. --report-file is relative to the site directory, like --output.
+	let reportFile: Option<Path> = if report { reportFileFlag.map(|file| path.join(file)) } else { None };
 	//Make sure the user gave us a directory and not a file. 
 	if !path.is_dir() {
-		println!("Error: Not a directory. Usage: ./rgen <path to site files>");
+		reportError(format!("{} is not a directory. {}", path.as_str().unwrap(), USAGE));
+		writeFailureReport(&reportFile);
+		return;
+	}
+	//Load rgen.toml. Every setting has a default, so the file is optional.
+	let config: Vec<(~str,~str)> = loadSiteConfig(&path);
+	let options = BuildOptions {
+		drafts: draftsFlag.unwrap_or(siteSetting(&config, "build.drafts", "false") == "true".to_owned()),
+		future: futureFlag.unwrap_or(siteSetting(&config, "build.future", "false") == "true".to_owned())
+	};
+	let baseURL = baseURLFlag.unwrap_or(siteSetting(&config, "baseURL", ""));
+	//e.g. production, staging or dev. Selects vars.<environment>.txt.
+	let environment = envFlag.unwrap_or(siteSetting(&config, "build.env", ""));
+	let varsFile = siteSetting(&config, "files.vars", "vars.txt");
+	let globalsFile = siteSetting(&config, "files.globals", "globals.txt");
+	//Pages with a publishDate after or an expiryDate before this are left out of the build.
+	let now = time::now().strftime("%Y-%m-%dT%H:%M:%S");
+	//Create the path to each of the types of data. Absolute directories in rgen.toml may be outside the site.
+	let pathToContent = path.join(siteSetting(&config, "directories.content", "content"));
+	let pathToInclude = path.join(siteSetting(&config, "directories.include", "include"));
+	let pathToResources = path.join(siteSetting(&config, "directories.resources", "resources"));
+	let pathToTemplates = path.join(siteSetting(&config, "directories.templates", "templates"));
+	let pathToOutput = path.join(outputFlag.unwrap_or(siteSetting(&config, "output", "output")));
+	let pathToData = path.join(siteSetting(&config, "directories.data", "data"));
+	//Output files that are never deleted, e.g. a CNAME file for the host.
+	let protectedFiles: Vec<~str> = siteSettingList(&config, "clean.protect");
+	//Without --report-file the report is rgen-report.json next to the output directory, not in it, so that it isn't
+	//published with the site and a failed build leaves the output as it was.
+	let reportPath: Option<Path> = if report { Some(reportFile.clone().unwrap_or(pathToOutput.dir_path().join(REPORT))) } else { None };
+	//The output directory is moved and cleaned on every build, so it must not hold or be inside the site's own files.
+	let sourceDirs = vec!(pathToContent.clone(), pathToInclude.clone(), pathToResources.clone(), pathToTemplates.clone(), pathToData.clone(), path.join(siteSetting(&config, "directories.archetypes", "archetypes")));
+	match outputClash(&pathToOutput, &path, &sourceDirs) {
+		Some(dir) => {
+			reportError(format!("The output directory {} would overwrite {}. Choose another output directory.", pathToOutput.as_str().unwrap(), dir.as_str().unwrap()));
+			writeFailureReport(&reportPath);
+			return;
+		},
+		None => { }
+	}
+	if !recoverOutput(&pathToOutput) {
+		writeFailureReport(&reportPath);
 		return;
 	}
-	//Create the path to each of the types of data.
-	let pathToContent = Path::new(path.as_str().unwrap() + "/content/");
-	let pathToInclude = Path::new(path.as_str().unwrap() + "/include/");
-	let pathToResources = Path::new(path.as_str().unwrap() + "/resources/");
-	let pathToTemplates = Path::new(path.as_str().unwrap() + "/templates/");
-	let pathToOutput = Path::new(path.as_str().unwrap() + "/output/");
+	//The site is built here and only replaces the output directory once all of it has been written.
+	let pathToStaging = siblingPath(&pathToOutput, "staging");
+	if pathToStaging.exists() {
+		//Left over from a build that failed.
+		match fs::rmdir_recursive(&pathToStaging) {
+			Ok(_) => { },
+			Err(_) => {
+				reportError(format!("Could not remove {}.", pathToStaging.as_str().unwrap()));
+				writeFailureReport(&reportPath);
+				return;
+			}
+		}
+	}
+
+	//Time spent in each stage of the build in nanoseconds, for the build report: (stage, time)
+	let mut timings: Vec<(~str, u64)> = Vec::new();
+	let buildStart = time::precise_time_ns();
+	let mut stageStart = buildStart;
 
 	//Now create vectors containing paths to each of the individual files of each type. 
 	let rawContentFiles: Vec<Path> = fs::walk_dir(&pathToContent).ok().unwrap().collect();
 	let rawIncludeFiles: Vec<Path> = fs::walk_dir(&pathToInclude).ok().unwrap().collect();
 	let rawResourceFiles: Vec<Path> = fs::walk_dir(&pathToResources).ok().unwrap().collect();
 	let rawTemplateFiles: Vec<Path> = fs::walk_dir(&pathToTemplates).ok().unwrap().collect();
+	//The data directory is optional.
+	let rawDataFiles: Vec<Path> = if pathToData.is_dir() { fs::walk_dir(&pathToData).ok().unwrap().collect() } else { Vec::new() };
 
 	//Remove directories and hidden files from the listing
 	let mut contentFiles: Vec<Path> = Vec::new();
 	let mut includeFiles: Vec<Path> = Vec::new();
 	let mut resourceFiles: Vec<Path> = Vec::new();
 	let mut templateFiles: Vec<Path> = Vec::new();
+	let mut dataFiles: Vec<Path> = Vec::new();
+	let mut shortcodeFiles: Vec<Path> = Vec::new();
 	//Print all files for testing purposes.
 	//Note that this prints directories and hidden files. We'll have to check for these later.
 	println!("Content Files:");
-	for p in rawContentFiles.iter() {
-		if !(p.is_dir() || p.filename_str().unwrap()[0] == 0x2E) {
-			contentFiles.push(Path::new(p));
+	let visibleContentFiles: Vec<Path> = rawContentFiles.iter().filter(|p| !(p.is_dir() || p.filename_str().unwrap()[0] == 0x2E)).map(|p| p.clone()).collect();
+	//Files beside the index file of a page bundle are copied next to the page, not loaded as content.
+	let bundleFiles: Vec<(Path, Path)> = bundleAssets(&visibleContentFiles, &pathToContent);
+	for p in visibleContentFiles.iter() {
+		if !bundleFiles.iter().any(|&(ref asset, _)| asset == p) {
+			contentFiles.push(p.clone());
 			println!("\t{}", str::from_utf8(p.as_vec()).unwrap());
 		}
 	}
+	println!("Bundle Files:");
+	for &(ref p, _) in bundleFiles.iter() {
+		println!("\t{}", str::from_utf8(p.as_vec()).unwrap());
+	}
 	println!("Include Files:");
 	for p in rawIncludeFiles.iter() {
-		if !(p.is_dir() || p.filename_str().unwrap()[0] == 0x2E || p.filename_str().unwrap() == "vars.txt") {
-			includeFiles.push(Path::new(p));
+		//vars.txt and per-language vars.<language>.txt files aren't includes.
+		let isVars = p.filename_str().unwrap() == varsFile.as_slice() || isLayeredFile(p.filename_str().unwrap(), varsFile);
+		//Files in include/shortcodes are shortcodes, not includes.
+		let isShortcode = match p.path_relative_from(&pathToInclude) {
+			Some(rel) => rel.as_str().unwrap().starts_with("shortcodes/"),
+			None => false
+		};
+		if !(p.is_dir() || p.filename_str().unwrap()[0] == 0x2E || isVars) {
+			if isShortcode {
+				shortcodeFiles.push(Path::new(p));
+			}
+			else {
+				includeFiles.push(Path::new(p));
+			}
 			println!("\t{}", str::from_utf8(p.as_vec()).unwrap());
 		}
 	}
@@ -63,336 +276,2490 @@ fn main() {
 			println!("\t{}", str::from_utf8(p.as_vec()).unwrap());
 		}
 	}
+	println!("Data Files:");
+	for p in rawDataFiles.iter() {
+		if !(p.is_dir() || p.filename_str().unwrap()[0] == 0x2E) {
+			dataFiles.push(Path::new(p));
+			println!("\t{}", str::from_utf8(p.as_vec()).unwrap());
+		}
+	}
 
-	//Map resource names: (name, path)
-	let resourceNames: Vec<(~str,~str)> = loadResourceNames(resourceFiles);
+	//Parse content and template files once. Files with syntax errors are left out and fail the build.
+	let parsedContent: Vec<(Path, SourceFile)> = parseFiles(&contentFiles, parseContentFile);
+	let parsedTemplates: Vec<(Path, SourceFile)> = parseFiles(&templateFiles, parseTemplateFile);
+
+	addTiming(&mut timings, "scan", &mut stageStart);
 
 	//Load vars.txt into vars, a vector of string tuples. Matched with %var or {%var}.
-	let vars: Vec<(~str,~str)> = loadVars(pathToInclude);
+	//Lists in vars files are looped over like lists in /data.
+	let (mut vars, mut varLists) = loadVars(&pathToInclude.join(varsFile.as_slice()));
+	if environment != "".to_owned() {
+		//vars.<environment>.txt on top of vars.txt. {%env} is the environment being built.
+		let envVarsPath = pathToInclude.join(layeredFileName(varsFile, environment));
+		if envVarsPath.exists() {
+			layerVars(&mut vars, &mut varLists, loadVars(&envVarsPath));
+		}
+		else {
+			warn(format!("There is no {} for the {} environment.", envVarsPath.as_str().unwrap(), environment));
+		}
+		setVar(&mut vars, "env".to_owned(), environment.clone());
+	}
+
+	//Vars that win over every vars file: RGEN_VAR_<name> environment variables, then --base-url.
+	let mut overrides: Vec<(~str,~str)> = loadEnvironmentVars();
+	if baseURL != "".to_owned() {
+		setVar(&mut overrides, "baseURL".to_owned(), baseURL.clone());
+	}
+	for var in overrides.iter() {
+		let (ref a, ref b) = *var;
+		setVar(&mut vars, a.clone(), b.clone());
+	}
+	//Where the site is on its host, e.g. /blog for a baseURL of https://example.com/blog/.
+	let hostPath = basePath(lookupVar(&vars, "baseURL").unwrap_or("".to_owned()));
+	//{%searchIndex} is the URL of the search index, for the data-index of the search script.
+	setVar(&mut vars, "searchIndex".to_owned(), hostPath + "/" + siteSetting(&config, "search.output", "search-index.json").trim_left_chars('/'));
 
-	//Load internal link names so that they can be replaced for includes. Matched with $link or {$link}
-	let internalLinks: Vec<(~str,~str)> = loadLinks(&contentFiles);
+	//Pages for the client-side search index, written to search.output when search.enabled is true.
+	let search = siteSetting(&config, "search.enabled", "false") == "true".to_owned();
 
-	//Process includes. Matched with {.include}
-	let includes: Vec<(~str,~str)> = loadIncludes(includeFiles, &vars, &internalLinks);
+	//Map resource names: (name, path)
+	let mut resourceNames: Vec<(~str,~str)> = loadResourceNames(&resourceFiles, &pathToResources, hostPath);
+	//Sites that don't have search.js of their own, e.g. sites made before search existed, get the one new sites have.
+	let builtInSearchJS = search && lookupVar(&resourceNames, "search.js").is_none();
+	if builtInSearchJS {
+		resourceNames.push(("search.js".to_owned(), hostPath + "/" + SEARCH_JS));
+	}
+
+	//Load /data files. Values are vars matched with {%data.file.key}, lists are looped over with {#for item in data.file}.
+	let (dataVars, dataLists) = loadData(dataFiles);
+	vars.push_all(dataVars.as_slice());
+
+	//Languages to build, default language first.
+	let languages: Vec<~str> = loadLanguages(&vars, &varLists);
+
+	//Read the config of every content file up front so that links and translations are known before any page is built.
+	let contentInfo: Vec<ContentInfo> = scanContent(&parsedContent, &pathToContent, &languages, now.as_slice(), &options, hostPath);
+	checkMenus(&contentInfo);
 
 	//Process global css/js
-	let globalCSSJS: Vec<~str> = loadGlobalCSSJS(pathToTemplates);
+	let globalCSSJS: Vec<~str> = loadGlobalCSSJS(pathToTemplates.join(globalsFile.as_slice()), hostPath);
 
-	//Load templates
-	let mut templatesPre: Vec<Template> = loadTemplates(templateFiles, &vars, &internalLinks, &includes);
+	//Process includes. Matched with {.include}
+	let mut includes: Vec<(~str,~str)> = loadIncludes(includeFiles.clone());
+	//Likewise the search box, {.search}.
+	if search && lookupVar(&includes, "search").is_none() {
+		includes.push(("search".to_owned(), newSiteFile(SEARCH_INCLUDE).to_owned()));
+	}
 
-	//Process template inheritance
-	let templates: Vec<Template> = processInheritance(&mut templatesPre);
+	//Load shortcodes. Used in content with {{< name arg="value" >}}.
+	let shortcodes: Vec<Shortcode> = loadShortcodes(shortcodeFiles);
 
-	//Load content
-	let mut content: Vec<Page> = loadContent(contentFiles, &vars, &internalLinks, &includes);
+	//Every file written to /output, relative to it.
+	let mut generated: Vec<~str> = Vec::new();
+	//Files that couldn't be written.
+	let mut failures = 0;
 
-	//Process content. Make block content and page content become HTML from Markdown.
-	mdToHTML(&mut content);
+	//Pages left out of the build: (content file, reason)
+	let mut excluded: Vec<(~str,~str)> = Vec::new();
 
-	//Generate content. Build full HTML by combining templates, blocks, and HTML content.
-	let htmlFiles: Vec<(~str,~str)> = processContent(content, templates, resourceNames, globalCSSJS);
+	//Every page generated, for the build report.
+	let mut pageReports: Vec<PageReport> = Vec::new();
 
-	//Then output to /output, making directory if it doesn't exist. 
-	outputFiles(htmlFiles, pathToOutput);
+	//Pages for the client-side search index, written to search.output.
+	let mut searchEntries: Vec<SearchEntry> = Vec::new();
 
-	//Copy all files from /resources to /output/resources. 
-}
+	//(old path, new path) of every alias, for the files in redirects.files.
+	let mut redirects: Vec<(~str,~str)> = Vec::new();
+	//Redirect stubs of every language. They are written last, leaving out any that would replace another file.
+	let mut aliasFiles: Vec<(~str,~str)> = Vec::new();
 
-fn loadResourceNames(resourceFiles: Vec<Path>) -> Vec<(~str,~str)> {
-	let mut resourceNames: Vec<(~str,~str)> = Vec::new();
-	for p in resourceFiles.iter() {
-		let pathStr = p.as_str().unwrap();
-		let fileNameStr = p.filename_str().unwrap().to_owned();
-		let mut index = 0;
-		match pathStr.find_str("/css/") {
-			Some(i) => { index = i },
-			None => { }
+	addTiming(&mut timings, "load", &mut stageStart);
+
+	//Build the site once for each language.
+	for language in languages.iter() {
+		//vars.txt with vars.<language>.txt on top of it.
+		let (mut langVars, langVarLists) = loadLanguageVars(&pathToInclude, varsFile.as_slice(), &vars, &varLists, language.as_slice());
+		for var in overrides.iter() {
+			let (ref a, ref b) = *var;
+			setVar(&mut langVars, a.clone(), b.clone());
 		}
-		match pathStr.find_str("/img/") {
-			Some(i) => { index = i },
-			None => { }
+		let mut lists = langVarLists;
+		lists.push_all(dataLists.as_slice());
+
+		//Load internal link names. Matched with $link or {$link}
+		//Links only point at pages in the language being built.
+		let internalLinks: Vec<(~str,~str)> = loadLinks(&contentInfo, language.as_slice());
+
+		//Load templates
+		let mut templatesPre: Vec<Template> = loadTemplates(&parsedTemplates, &includes, hostPath);
+
+		//Process template inheritance
+		let templates: Vec<Template> = processInheritance(&mut templatesPre);
+		addTiming(&mut timings, "templates", &mut stageStart);
+
+		//Load content
+		let mut langContentFiles: Vec<(Path, SourceFile)> = Vec::new();
+		for (parsed, info) in parsedContent.iter().zip(contentInfo.iter()) {
+			if info.language == *language {
+				langContentFiles.push(parsed.clone());
+			}
+		}
+		let loadedContent: Vec<Page> = loadContent(langContentFiles, &pathToContent, &langVars, &internalLinks, &includes, hostPath);
+
+		//Leave drafts, scheduled and expired pages out of the build.
+		let mut content: Vec<Page> = Vec::new();
+		for page in loadedContent.move_iter() {
+			let mut page = page;
+			let reason = exclusionReason(page.draft, page.publishDate.as_slice(), page.expiryDate.as_slice(), now.as_slice(), &options);
+			match reason {
+				Some(reason) => { excluded.push((page.sourcePath.clone(), reason)) },
+				None => {
+					let (_, translationKey) = contentLanguage(page.sourcePath.as_slice(), &languages);
+					page.language = language.to_owned();
+					page.path = languagePrefix(language.as_slice(), &languages) + page.path.trim();
+					page.headData.push_all(hreflangLinks(translationKey.as_slice(), &contentInfo, hostPath).as_slice());
+					content.push(page);
+				}
+			}
+		}
+		addTiming(&mut timings, "content", &mut stageStart);
+
+		//Process content. Make block content and page content become HTML from Markdown.
+		//Shortcodes that produce Markdown are expanded before that and the rest after.
+		expandPageShortcodes(&mut content, &shortcodes, true);
+		mdToHTML(&mut content, &langVars);
+		expandPageShortcodes(&mut content, &shortcodes, false);
+		addTiming(&mut timings, "markdown", &mut stageStart);
+
+		if search {
+			addSearchEntries(&content, &langVars, &internalLinks, hostPath, &mut searchEntries);
 		}
-		match pathStr.find_str("/js/") {
-			Some(i) => { index = i },
+
+		//Redirect stubs at the old paths of pages.
+		aliasFiles.push_all(aliasPages(&content, &langVars, &contentInfo, hostPath, &mut redirects).as_slice());
+
+		//Generate content. Build full HTML by combining templates, blocks, and HTML content.
+		let htmlFiles: Vec<(~str,~str)> = processContent(content, templates, resourceNames.clone(), globalCSSJS.clone(), &langVars, &internalLinks, &lists, &contentInfo, hostPath, &mut pageReports);
+		addTiming(&mut timings, "render", &mut stageStart);
+
+		//Then output to /output, making directory if it doesn't exist. 
+		let fileCount = htmlFiles.len();
+		let written = outputFiles(htmlFiles, pathToStaging.clone());
+		failures += fileCount - written.len();
+		generated.push_all(written.as_slice());
+		addTiming(&mut timings, "write", &mut stageStart);
+	}
+
+	if search {
+		let inverted = siteSetting(&config, "search.inverted", "false") == "true".to_owned();
+		let searchFile = siteSetting(&config, "search.output", "search-index.json");
+		let written = outputFiles(vec!((searchFile, searchIndex(&searchEntries, inverted))), pathToStaging.clone());
+		failures += 1 - written.len();
+		generated.push_all(written.as_slice());
+	}
+
+	//Copy all files from /resources to /output/resources. 
+	let mut copied = copyResources(&resourceFiles, &pathToResources, &pathToStaging);
+	failures += resourceFiles.len() - copied.len();
+	//Copy the files of page bundles next to their pages.
+	let (bundleCopied, bundleFailures) = copyBundleFiles(&bundleFiles, &contentInfo, &pathToStaging);
+	failures += bundleFailures;
+	copied.push_all(bundleCopied.as_slice());
+	generated.push_all(copied.as_slice());
+	if builtInSearchJS {
+		let written = outputFiles(vec!((SEARCH_JS.to_owned(), newSiteFile(SEARCH_JS).to_owned())), pathToStaging.clone());
+		failures += 1 - written.len();
+		generated.push_all(written.as_slice());
+	}
+	addTiming(&mut timings, "resources", &mut stageStart);
+
+	//Alias stubs go wherever nothing else was written: not over a page, a later page of a listing, the search index,
+	//a resource, a bundle file, a redirects file or the manifest.
+	let redirectFormats = siteSettingList(&config, "redirects.files");
+	let mut staged = generated.clone();
+	for format in redirectFormats.iter() {
+		match redirectFileName(format.as_slice()) {
+			Some(name) => { staged.push(name.to_owned()) },
 			None => { }
 		}
-		resourceNames.push((fileNameStr, pathStr.slice_from(index).to_owned()));
 	}
-	return resourceNames;
-}
+	staged.push(MANIFEST.to_owned());
+	let aliasStubs = dropAliasClashes(aliasFiles, &staged, &mut redirects);
+	let aliasCount = aliasStubs.len();
+	let written = outputFiles(aliasStubs, pathToStaging.clone());
+	failures += aliasCount - written.len();
+	generated.push_all(written.as_slice());
 
-fn loadVars(pathToInclude: Path) -> Vec<(~str,~str)> {
-	let mut vars: Vec<(~str,~str)> = Vec::new();
-	let varsPath = Path::new(pathToInclude.as_str().unwrap() + "/vars.txt");
-	let mut varReader = BufferedReader::new(File::open(&varsPath));
-	for line in varReader.lines() {
-		let st = line.unwrap().to_owned();
-		let v: Vec<&str> = st.split_str(": ").collect();
-		let temp: (~str, ~str) = (v.get(0).trim().to_owned(), v.get(1).trim().to_owned());
-		vars.push(temp);
+	let hostFiles = redirectFiles(&redirectFormats, &redirects);
+	if hostFiles.len() > 0 {
+		let written = outputFiles(hostFiles.clone(), pathToStaging.clone());
+		failures += hostFiles.len() - written.len();
+		generated.push_all(written.as_slice());
 	}
-	return vars;
-}
 
-fn loadIncludes(includeFiles: Vec<Path>, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>) -> Vec<(~str,~str)> {
-	let mut returnVec: Vec<(~str,~str)> = Vec::new();
-	for p in includeFiles.iter() {
-		let fileName = p.filestem_str().unwrap().to_owned();
-		let mut fileReader = BufferedReader::new(File::open(p));
-		let fileContent = replaceVars(fileReader.read_to_str().unwrap().to_owned(), vars, internalLinks);
-		//println!("{}", fileContent);
-		returnVec.push((fileName, fileContent));
+	if excluded.len() > 0 {
+		println!("Excluded pages:");
+		for page in excluded.iter() {
+			let (ref a, ref b) = *page;
+			println!("\t{} ({})", *a, *b);
+		}
 	}
-	return returnVec;
-}
 
-//This method is slow. Fix it. 
-fn loadLinks(contentFiles: &Vec<Path>) -> Vec<(~str,~str)> {
-	let mut returnVec: Vec<(~str,~str)> = Vec::new();
-	for p in contentFiles.iter() {
-		let mut fileReader = BufferedReader::new(File::open(p));
-		let mut linkName = "".to_owned();
-		let mut linkPath = "".to_owned();
-		for line in fileReader.lines() {
-			let st = line.unwrap();
-			if st.starts_with("\tlinkName:") {
-				linkName = st.split_str(":").last().unwrap().trim().to_owned();
-			}
-			else if st.starts_with("\tpath:") {
-				linkPath = st.split_str(":").last().unwrap().trim().to_owned();
-			}
-			if linkName != "".to_owned() && linkPath != "".to_owned() {
-				break;
-			}
+	let errors = match ERRORS.replace(None) {
+		Some(errors) => errors,
+		None => Vec::new()
+	};
+	let mut success = failures == 0 && errors.len() == 0;
+	if success {
+		//Swap the new build in for the old one.
+		success = publishStaging(&pathToStaging, &pathToOutput, &generated, &protectedFiles, clean);
+	}
+	else {
+		if errors.len() > 0 {
+			println!("Error: There were {} errors in site files. {} was left as it was.", errors.len(), pathToOutput.as_str().unwrap());
 		}
-		if linkName == "".to_owned() || linkPath == "".to_owned() {
-			println!("Warning: linkName or linkPath for content file {} is not set.", p.filename_str().unwrap());
+		if failures > 0 {
+			println!("Error: {} files could not be written. {} was left as it was.", failures, pathToOutput.as_str().unwrap());
 		}
-		else {
-			returnVec.push((linkName, linkPath));
+		match fs::rmdir_recursive(&pathToStaging) {
+			Ok(_) => { },
+			Err(_) => { }
 		}
 	}
-	return returnVec;
+	addTiming(&mut timings, "publish", &mut stageStart);
+	timings.push(("total".to_owned(), time::precise_time_ns() - buildStart));
+
+	match reportPath {
+		Some(ref reportPath) => {
+			let warnings = match WARNINGS.replace(None) {
+				Some(warnings) => warnings,
+				None => Vec::new()
+			};
+			if writeReport(reportPath, success, &pageReports, &copied, &excluded, &errors, &warnings, &timings) {
+				println!("Build report written to {}.", reportPath.as_str().unwrap());
+			}
+			else {
+				println!("Error: Could not write {}.", reportPath.as_str().unwrap());
+				success = false;
+			}
+		},
+		None => { }
+	}
+
+	if !success {
+		os::set_exit_status(1);
+	}
 }
 
-fn loadGlobalCSSJS(pathToTemplates: Path) -> Vec<~str> {
-	let mut returnVec: Vec<~str> = Vec::new();
-	let globalPath = Path::new(pathToTemplates.as_str().unwrap() + "/globals.txt");
-	let mut fileReader = BufferedReader::new(File::open(&globalPath));
-	let line1 = fileReader.read_line().unwrap();
-	let css = line1.trim() == "css";
-	let mut js = line1.trim() == "js";
-	while css {
-		let nextLine = fileReader.read_line();
-		match nextLine {
-			Ok(tex) => {
-				let texOwned = tex.trim();
-				if texOwned == "js" {
-					js = true;
-					break;
-				}
-				else {
-					returnVec.push("<link rel='stylesheet' type='text/css' href='resources/css/" + texOwned + "'>");
-				}
-			},
-			Err(_) => { break }
+//The search box include and script of new sites. Builds with search enabled use them if the site has none of its own.
+static SEARCH_INCLUDE: &'static str = "include/search.html";
+static SEARCH_JS: &'static str = "resources/js/search.js";
+
+//The content of a file new sites have, e.g. SEARCH_JS.
+fn newSiteFile(name: &str) -> &'static str {
+	for &(path, content) in NEW_SITE_FILES.iter() {
+		if path == name {
+			return content;
 		}
 	}
-	while js {
-		let nextLine = fileReader.read_line();
-		match nextLine {
-			Ok(tex) => {
-				let texOwned = tex.trim();
-				returnVec.push("<script type='text/javascript' src='resources/js/" + texOwned + "'></script>");
-			},
-			Err(_) => { break }
+	return "";
+}
+
+//Files of a new site: (path in the site, content). The site builds as it is and shows how each kind of file is written.
+static NEW_SITE_FILES: &'static [(&'static str, &'static str)] = &[
+	("rgen.toml", "# Site settings. Every setting is optional and these are the defaults.\n\
+baseURL = \"\"\n\
+output = \"output\"\n\
+\n\
+[directories]\n\
+content = \"content\"\n\
+include = \"include\"\n\
+resources = \"resources\"\n\
+templates = \"templates\"\n\
+data = \"data\"\n\
+\n\
+[files]\n\
+vars = \"vars.txt\"\n\
+globals = \"globals.txt\"\n\
+\n\
+[build]\n\
+drafts = false\n\
+future = false\n\
+# Layers include/vars.<env>.txt over vars.txt, e.g. env = \"production\". --env overrides it.\n\
+env = \"\"\n\
+\n\
+# Files in the output directory that --clean and stale file removal never delete.\n\
+[clean]\n\
+protect = [\"CNAME\"]\n\
+\n\
+# A JSON index of every page for client-side search. Put {.search} in a template for a search box.\n\
+# include/search.html and resources/js/search.js can be changed. Without them rgen uses its own.\n\
+# inverted = true writes a term index instead of the text of each page, which is smaller for big sites.\n\
+[search]\n\
+enabled = false\n\
+output = \"search-index.json\"\n\
+inverted = false\n\
+\n\
+# Files listing the redirects made for the aliases of pages, for hosts: \"netlify\" (_redirects) and \"nginx\" (redirects.map).\n\
+[redirects]\n\
+files = []\n"),
+	("include/vars.txt", "# name: value. Quote values to keep spaces or use escapes; [a, b] is a list for {#for}.\n\
+siteName: My rgen Site\n\
+tagline: Built with rgen\n"),
+	("include/header.html", "<header>\n\
+<a class='site-name' href='{$Home}'>{%siteName}</a>\n\
+{nav}\n\
+</header>\n"),
+	("include/footer.html", "<footer>\n\
+<p>{%tagline}</p>\n\
+</footer>\n"),
+	("templates/globals.txt", "css\n\
+style.css\n"),
+	("templates/base.html", "blocks\n\
+\tfeature\n\
+\t\t<section class='feature'>\n\
+\t\t<h2>{title=Featured}</h2>\n\
+\t\t{body}\n\
+\t\t</section>\n\
+\n\
+<!DOCTYPE html>\n\
+<html>\n\
+<head>\n\
+<meta charset='utf-8'>\n\
+</head>\n\
+<body>\n\
+{.header}\n\
+{content}\n\
+{#region footer}\n\
+{.footer}\n\
+{/region}\n\
+</body>\n\
+</html>\n"),
+	("templates/page.html", "inherit base\n\
+blocks\n\
+\tnote\n\
+\t\t<aside class='note'>{text}</aside>\n\
+\n\
+<main>\n\
+<h1>{%page.title}</h1>\n\
+{content}\n\
+{feature}\n\
+{note}\n\
+</main>\n"),
+	("content/index.md", "config\n\
+\tpath: index.html\n\
+\tlinkName: Home\n\
+\ttitle: Welcome\n\
+\tdescription: A site built with rgen.\n\
+\ttemplate: page\n\
+\tmenu: main\n\
+\tweight: 1\n\
+blocks\n\
+\tfeature\n\
+\t\ttitle\n\
+\t\t\tFast\n\
+\t\tbody\n\
+\t\t\tPages are plain Markdown files.\n\
+\n\
+Welcome to your new site. Edit `content/index.md` to change this page,\n\
+or see the [about page]({$About}).\n"),
+	("content/about.md", "config\n\
+\tpath: about.html\n\
+\tlinkName: About\n\
+\ttitle: About\n\
+\ttemplate: page\n\
+\tmenu: main\n\
+\tweight: 2\n\
+blocks\n\
+\n\
+## About this site\n\
+\n\
+This page uses the *page* template, which inherits from *base*.\n"),
+	("resources/css/style.css", "body {\n\
+\tfont-family: sans-serif;\n\
+\tmax-width: 40em;\n\
+\tmargin: 0 auto;\n\
+}\n\
+\n\
+.nav li {\n\
+\tdisplay: inline;\n\
+\tmargin-right: 1em;\n\
+}\n\
+\n\
+.nav .active a {\n\
+\tfont-weight: bold;\n\
+}\n"),
+	("include/search.html", "<form id='search' role='search'>\n\
+<input type='search' name='q' placeholder='Search' aria-label='Search'>\n\
+</form>\n\
+<ul id='search-results'></ul>\n\
+<script src='{$search.js}' data-index='{%searchIndex}'></script>\n"),
+	("resources/js/search.js", "//Client-side search over the index rgen writes when search is enabled in rgen.toml.\n\
+//Works with the plain index and with the smaller inverted one (search.inverted = true).\n\
+(function() {\n\
+\tvar script = document.currentScript;\n\
+\tvar form = document.getElementById('search');\n\
+\tvar results = document.getElementById('search-results');\n\
+\tvar index = null;\n\
+\n\
+\t//Lowercase words of at least two letters, split the way rgen splits them for the inverted index.\n\
+\tfunction words(text) {\n\
+\t\treturn text.toLowerCase().split(/[^\\p{L}\\p{N}]+/u).filter(function(word) { return word.length > 1; });\n\
+\t}\n\
+\n\
+\tfunction escapeHTML(text) {\n\
+\t\treturn text.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;').replace(/'/g, '&#39;');\n\
+\t}\n\
+\n\
+\t//Score of each page for the query, by page number. Every word has to match, the start of a word is enough.\n\
+\tfunction scores(query) {\n\
+\t\tvar total = {};\n\
+\t\tvar queryWords = words(query);\n\
+\t\tqueryWords.forEach(function(word, n) {\n\
+\t\t\tvar found = {};\n\
+\t\t\tif (index.index) {\n\
+\t\t\t\tObject.keys(index.index).forEach(function(term) {\n\
+\t\t\t\t\tif (term.indexOf(word) == 0) {\n\
+\t\t\t\t\t\tindex.index[term].forEach(function(entry) {\n\
+\t\t\t\t\t\t\tfound[entry[0]] = (found[entry[0]] || 0) + entry[1];\n\
+\t\t\t\t\t\t});\n\
+\t\t\t\t\t}\n\
+\t\t\t\t});\n\
+\t\t\t}\n\
+\t\t\telse {\n\
+\t\t\t\tindex.pages.forEach(function(page, i) {\n\
+\t\t\t\t\tvar score = 0;\n\
+\t\t\t\t\twords(page.title).forEach(function(w) { if (w.indexOf(word) == 0) score += 3; });\n\
+\t\t\t\t\tpage.headings.forEach(function(h) {\n\
+\t\t\t\t\t\twords(h.text).forEach(function(w) { if (w.indexOf(word) == 0) score += 2; });\n\
+\t\t\t\t\t});\n\
+\t\t\t\t\tpage.tags.forEach(function(t) { if (t.toLowerCase().indexOf(word) == 0) score += 2; });\n\
+\t\t\t\t\twords(page.text).forEach(function(w) { if (w.indexOf(word) == 0) score += 1; });\n\
+\t\t\t\t\tif (score > 0) found[i] = score;\n\
+\t\t\t\t});\n\
+\t\t\t}\n\
+\t\t\tObject.keys(total).forEach(function(i) { if (!(i in found)) delete total[i]; });\n\
+\t\t\tObject.keys(found).forEach(function(i) {\n\
+\t\t\t\tif (n == 0 || i in total) total[i] = (total[i] || 0) + found[i];\n\
+\t\t\t});\n\
+\t\t});\n\
+\t\treturn total;\n\
+\t}\n\
+\n\
+\tfunction show(query) {\n\
+\t\tvar total = scores(query);\n\
+\t\tvar found = Object.keys(total).sort(function(a, b) { return total[b] - total[a]; }).slice(0, 20);\n\
+\t\tvar html = '';\n\
+\t\tfound.forEach(function(i) {\n\
+\t\t\tvar page = index.pages[i];\n\
+\t\t\thtml += '<li><a href=\\'' + escapeHTML(page.path) + '\\'>' + escapeHTML(page.title) + '</a>';\n\
+\t\t\tif (page.description) html += '<p>' + escapeHTML(page.description) + '</p>';\n\
+\t\t\thtml += '</li>';\n\
+\t\t});\n\
+\t\tresults.innerHTML = query.trim() == '' ? '' : (html || '<li>Nothing found.</li>');\n\
+\t}\n\
+\n\
+\tform.addEventListener('input', function() {\n\
+\t\tvar query = form.q.value;\n\
+\t\tif (index) {\n\
+\t\t\tshow(query);\n\
+\t\t\treturn;\n\
+\t\t}\n\
+\t\tfetch(script.getAttribute('data-index') || '/search-index.json').then(function(response) {\n\
+\t\t\treturn response.json();\n\
+\t\t}).then(function(data) {\n\
+\t\t\tindex = data;\n\
+\t\t\tshow(form.q.value);\n\
+\t\t});\n\
+\t});\n\
+\tform.addEventListener('submit', function(event) { event.preventDefault(); });\n\
+})();\n")
+];
+
+//Create a new site at path with every directory rgen uses and a few sample files.
+fn newSite(path: &Path) {
+	if path.exists() && fs::readdir(path).ok().map_or(false, |entries| entries.len() > 0) {
+		println!("Error: {} already exists and is not empty.", path.as_str().unwrap());
+		return;
+	}
+	for &(name, content) in NEW_SITE_FILES.iter() {
+		if !writeNewFile(&path.join(name), content) {
+			return;
 		}
 	}
-	/*println!("Global includes:");
-	for elem in returnVec.iter() {
-		println!("{}", elem);
-	}*/
-	return returnVec;
+	match fs::mkdir_recursive(&path.join("data"), io::UserRWX) {
+		Ok(_) => { },
+		Err(_) => { }
+	}
+	println!("Created a new site in {}. Build it with ./rgen {}", path.as_str().unwrap(), path.as_str().unwrap());
 }
 
-struct Template {
-	name: ~str,
-	inherit: ~str,
-	headData: Vec<~str>,
-	blockTemplates: Vec<(~str,~str)>,
-	content: ~str
+//Whether there is a template called name, e.g. templates/page.html for page.
+fn templateExists(pathToTemplates: &Path, name: &str) -> bool {
+	return match fs::readdir(pathToTemplates) {
+		Ok(files) => files.iter().any(|p| !p.is_dir() && p.filestem_str() == Some(name)),
+		Err(_) => false
+	};
 }
 
-impl Clone for Template {
-	fn clone(&self) -> Template {
-		let myTemplate = Template {name: self.name.clone(), inherit: self.inherit.clone(), headData: self.headData.clone(), blockTemplates: self.blockTemplates.clone(), content: self.content.clone()};
-		return myTemplate;
+//Create a content file from an archetype in the archetypes directory: archetypes/<kind>.md with --kind, otherwise the
+//archetype named after the page's section (its first directory), otherwise archetypes/default.md.
+//The archetype is a content file. path, linkName, title and date are filled in from pagePath and today's date,
+//and template defaults to the archetype's name.
+fn newPage(sitePath: &Path, pagePath: &str, kind: Option<~str>) {
+	let config = loadSiteConfig(sitePath);
+	let pathToContent = sitePath.join(siteSetting(&config, "directories.content", "content"));
+	let pathToArchetypes = sitePath.join(siteSetting(&config, "directories.archetypes", "archetypes"));
+	let pathToTemplates = sitePath.join(siteSetting(&config, "directories.templates", "templates"));
+	let file = pathToContent.join(pagePath);
+	if file.exists() {
+		println!("Error: {} already exists.", file.as_str().unwrap());
+		os::set_exit_status(1);
+		return;
 	}
-}
 
-enum TemplateStep {
-	InInherit,
-	InCSS,
-	InJS,
-	InBlocks,
-	InContent
-}
+	//about/our-team.md becomes about/our-team.html with linkName and title "Our Team".
+	let stem = Path::new(pagePath).filestem_str().unwrap().to_owned();
+	let outputPath = match pagePath.rfind('.') {
+		Some(i) => pagePath.slice_to(i) + ".html",
+		None => pagePath + ".html"
+	};
+	let mut title = "".to_owned();
+	for word in stem.split(|c: char| c == '-' || c == '_' || c == ' ') {
+		if word != "" {
+			let first = word.char_at(0);
+			if !title.is_empty() {
+				title.push_char(' ');
+			}
+			title.push_char(first.to_uppercase());
+			title.push_str(word.slice_from(first.len_utf8_bytes()));
+		}
+	}
 
-//This hasn't been tested so if something is going wrong it's probably here.
-fn loadTemplates(templateFiles: Vec<Path>, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, includes: &Vec<(~str,~str)>) -> Vec<Template> {
-	let mut returnVec: Vec<Template> = Vec::new();
-	for file in templateFiles.iter() {
-		let mut myTemplate = Template {name: file.filestem_str().unwrap().to_owned(), inherit: "".to_owned(), headData: Vec::new(), blockTemplates: Vec::new(), content: "".to_owned()};
-		let mut fileReader = BufferedReader::new(File::open(file));
-		let mut curLine = fileReader.read_line().unwrap().trim().to_owned();
-		let mut curBlockName = "".to_owned();
-		let mut curBlockContent = "".to_owned();
-		let mut myStep: TemplateStep = InContent;
-		if curLine.starts_with("inherit ") {
-			myStep = InInherit;
-			let inheritance = curLine.split_str(" ").last().unwrap();
-			myTemplate.inherit = inheritance.to_owned();
-		}
-		else if curLine.starts_with("css") {
-			myStep = InCSS;
-		}
-		else if curLine.starts_with("js") {
-			myStep = InJS;
-		}
-		else if curLine.starts_with("blocks") {
-			myStep = InBlocks;
+	//Refuse to create a page that would clash with an existing one.
+	let allFiles: Vec<Path> = if pathToContent.is_dir() { fs::walk_dir(&pathToContent).ok().unwrap().filter(|p| !p.is_dir()).collect() } else { Vec::new() };
+	let assets = bundleAssets(&allFiles, &pathToContent);
+	let existingFiles: Vec<Path> = allFiles.iter().filter(|p| !assets.iter().any(|&(ref asset, _)| asset == *p)).map(|p| p.clone()).collect();
+	let parsedFiles = parseFiles(&existingFiles, parseContentFile);
+	let allPages = BuildOptions { drafts: true, future: true };
+	for info in scanContent(&parsedFiles, &pathToContent, &vec!("".to_owned()), "", &allPages, "").iter() {
+		if info.linkName == title {
+			println!("Error: {} already has the linkName {}.", info.file.as_str().unwrap(), title);
+			os::set_exit_status(1);
+			return;
 		}
-		loop {
-			let nextLine = fileReader.read_line();
-			match nextLine {
-				Ok(tex) => { curLine = tex.to_owned() },
-				Err(_) => { break }
+		if info.path == outputPath {
+			println!("Error: {} is already output to {}.", info.file.as_str().unwrap(), outputPath);
+			os::set_exit_status(1);
+			return;
+		}
+	}
+
+	let section = if pagePath.contains_char('/') { pagePath.slice_to(pagePath.find('/').unwrap()).to_owned() } else { "".to_owned() };
+	let mut archetypeName = "default".to_owned();
+	let mut candidates: Vec<~str> = Vec::new();
+	let hasKind = kind.is_some();
+	match kind {
+		Some(k) => { candidates.push(k) },
+		None => {
+			if section != "".to_owned() {
+				candidates.push(section);
 			}
-			let mut advanced = false;
-			match curLine.trim() {
-				"css" => {
-					myStep = InCSS;
-					advanced = true;
-				},
-				"js" => {
-					myStep = InJS;
-					advanced = true;
-				},
-				"blocks" => {
-					myStep = InBlocks;
-					advanced = true;
-				},
-				"" => {
-					advanced = true; //Either we advance or this line is blank so ignore it regardless
-					match myStep {
-						InBlocks => { myStep = InContent; advanced = true; },
-						_ => { }
-					}
-				},
-				_ => { }
+			candidates.push("default".to_owned());
+		}
+	}
+	let mut archetype = "".to_owned();
+	let mut found = false;
+	for candidate in candidates.iter() {
+		let archetypePath = pathToArchetypes.join(*candidate + ".md");
+		if !found && archetypePath.exists() {
+			let mut fileReader = BufferedReader::new(File::open(&archetypePath));
+			archetype = fileReader.read_to_str().unwrap();
+			archetypeName = candidate.clone();
+			found = true;
+		}
+	}
+	//Without --kind a page can be made without an archetype, e.g. in a new site, which has none.
+	if !found && hasKind {
+		println!("Error: There is no archetype {}.", pathToArchetypes.join(*candidates.get(0) + ".md").as_str().unwrap());
+		os::set_exit_status(1);
+		return;
+	}
+
+	//Config values in the order they are written. The archetype's own values are kept unless they are filled in here.
+	let mut pageConfig: Vec<(~str,~str)> = Vec::new();
+	let mut body = "blocks\n\n# {%page.title}\n".to_owned();
+	if found {
+		let mut inConfig = false;
+		let mut bodyLines: Vec<~str> = Vec::new();
+		for line in archetype.lines() {
+			if line.trim() == "config" && pageConfig.len() == 0 && bodyLines.len() == 0 {
+				inConfig = true;
 			}
-			if !advanced {
-				let curLineUnTrimmed = curLine.to_owned();
-				curLine = curLine.trim().to_owned();
-				//Replace variables/links/includes if we might need to.
-				if curLine.contains_char('{') && curLine.contains_char('}') {
-					//Check if there are variables/links and replace them.
-					if curLine.contains("{$") || curLine.contains("{%") {
-						curLine = replaceVars(curLine, vars, internalLinks);
-					}
-					//Check if there are includes and insert them.
-					if curLine.contains("{.") {
-						curLine = insertIncludes(curLine, includes);
-					}
-				}
-				match myStep {
-					InInherit => { }, //Inheritance was taken care of by the first line so do nothing here.
-					InCSS => {
-						myTemplate.headData.push("<link rel='stylesheet' type='text/css' href='resources/css/" + curLine + "'>");
-					},
-					InJS => {
-						myTemplate.headData.push("<script type='text/javascript' src='resources/js/" + curLine + "'></script>");
-					},
-					InBlocks => {
-						//Support double tab or 8 spaces. This is not very flexible. 
-						if curLineUnTrimmed.starts_with("\t\t") || curLineUnTrimmed.starts_with("        ") {
-							//In a block
-							curBlockContent = curBlockContent + "\n" + curLine;
-						}
-						else {
-							//Found a new block
-							let curBlock: (~str,~str) = (curBlockName.clone(), curBlockContent.clone());
-							myTemplate.blockTemplates.push(curBlock);
-							curBlockName = "".to_owned();
-							curBlockContent = "".to_owned();
-						}
-					},
-					InContent => {
-						myTemplate.content = curLine;
-						match fileReader.read_to_str() {
-							Ok(tex) => {
-								myTemplate.content = myTemplate.content + "\n" + tex;
-							},
-							Err(_) => {}
-						}
-					}
-				}
+			else if inConfig && (line.starts_with("\t") || line.starts_with(" ")) {
+				setVar(&mut pageConfig, line.trim().split(':').next().unwrap().trim().to_owned(), configValue(line));
+			}
+			else {
+				inConfig = false;
+				bodyLines.push(line.to_owned());
 			}
 		}
-		returnVec.push(myTemplate);
+		body = "".to_owned();
+		for line in bodyLines.iter() {
+			body = body + *line + "\n";
+		}
 	}
-	return returnVec;
-}
+	setVar(&mut pageConfig, "path".to_owned(), outputPath);
+	setVar(&mut pageConfig, "linkName".to_owned(), title.clone());
+	setVar(&mut pageConfig, "title".to_owned(), title);
+	if lookupVar(&pageConfig, "template").is_none() {
+		//The template named after the archetype, or otherwise page, the child template new sites have.
+		let template = if templateExists(&pathToTemplates, archetypeName) { archetypeName } else { "page".to_owned() };
+		if !templateExists(&pathToTemplates, template) {
+			warn(format!("There is no template {} in {}. Set the template of {} to one that exists.", template, pathToTemplates.as_str().unwrap(), pagePath));
+		}
+		setVar(&mut pageConfig, "template".to_owned(), template);
+	}
+	setVar(&mut pageConfig, "date".to_owned(), time::now().strftime("%Y-%m-%d"));
 
-fn replaceVars(mut text: ~str, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>) -> ~str {
-	for var in vars.iter() {
-		let (ref a, ref b) = *var;
-		text = text.replace("{%" + *a + "}", *b);
+	let mut fileContent = "config\n".to_owned();
+	for value in pageConfig.iter() {
+		let (ref a, ref b) = *value;
+		fileContent = fileContent + "\t" + *a + ": " + *b + "\n";
 	}
-	for link in internalLinks.iter() {
-		let (ref a, ref b) = *link;
-		text = text.replace("{$" + *a + "}", *b);
+	fileContent = fileContent + body;
+	if writeNewFile(&file, fileContent) {
+		println!("Created {}", file.as_str().unwrap());
+	}
+	else {
+		os::set_exit_status(1);
 	}
-	return text
 }
 
-fn insertIncludes(mut text: ~str, includes: &Vec<(~str,~str)>) -> ~str {
-	for include in includes.iter() {
-		let (ref a, ref b) = *include;
-		text = text.replace("{." + *a + "}", *b);
+//Write a file, creating its directory if needed. Returns false and prints an error if that fails.
+fn writeNewFile(path: &Path, content: &str) -> bool {
+	match fs::mkdir_recursive(&path.dir_path(), io::UserRWX) {
+		Ok(_) => { },
+		Err(_) => { }
+	}
+	let mut writer = BufferedWriter::new(File::create(path));
+	match writer.write_str(content).and_then(|_| writer.flush()) {
+		Ok(_) => { return true; },
+		Err(_) => {
+			println!("Error: Could not write {}.", path.as_str().unwrap());
+			return false;
+		}
 	}
-	return text;
 }
 
-//It's probably possible to clean this up a little bit. 
-fn processInheritance(templatesPre: &mut Vec<Template>) -> Vec<Template> {
-	let mut templates: Vec<Template> = Vec::new();
-	let oldTemplates = templatesPre.clone();
-	for template in templatesPre.mut_iter() {
-		if template.inherit != "".to_owned() {
-			for template2 in oldTemplates.iter() {
-				if template2.name == template.inherit {
-					template.headData.push_all(template2.headData.as_slice());
-					template.blockTemplates.push_all(template2.blockTemplates.as_slice());
-					template.content = template2.content.replace("{content}", template.content);
-				}
-			}
+//Why a page is left out of the build, or None if it should be built.
+//Dates are compared as text, so they must be written as YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS.
+fn exclusionReason(draft: bool, publishDate: &str, expiryDate: &str, now: &str, options: &BuildOptions) -> Option<~str> {
+	if draft && !options.drafts {
+		return Some("draft".to_owned());
+	}
+	if !options.future {
+		if publishDate != "" && publishDate > now {
+			return Some("scheduled for " + publishDate);
+		}
+		if expiryDate != "" && expiryDate <= now {
+			return Some("expired on " + expiryDate);
 		}
-		templates.push(template.clone());
 	}
-	return templates;
+	return None;
 }
 
-struct Page {
-	path: ~str,
-	linkName: ~str,
-	title: ~str,
-	template: ~str,
-	blocks: Vec<Block>,
-	headData: Vec<~str>,
-	content: ~str
+//Everything after the first colon of a config line, so values may contain colons themselves.
+fn configValue(line: &str) -> ~str {
+	match line.find(':') {
+		Some(i) => line.slice_from(i + 1).trim().to_owned(),
+		None => "".to_owned()
+	}
+}
+
+//Map each resource's file name to where copyResources puts it, e.g. search.js to /resources/js/search.js.
+//hostPath is where the site is on its host, e.g. /blog, or empty.
+fn loadResourceNames(resourceFiles: &Vec<Path>, pathToResources: &Path, hostPath: &str) -> Vec<(~str,~str)> {
+	let mut resourceNames: Vec<(~str,~str)> = Vec::new();
+	for p in resourceFiles.iter() {
+		let url = match p.path_relative_from(pathToResources) {
+			Some(rel) => format!("{}/resources/{}", hostPath, rel.as_str().unwrap()),
+			None => { continue }
+		};
+		let fileNameStr = p.filename_str().unwrap().to_owned();
+		match lookupVar(&resourceNames, fileNameStr) {
+			Some(other) => { warn(format!("{} and {} are both called {}. {} links to {}. Files that belong to one page can go in a page bundle beside its index file instead.", other, url, fileNameStr, "{$" + fileNameStr + "}", other)) },
+			None => { }
+		}
+		resourceNames.push((fileNameStr, url));
+	}
+	return resourceNames;
+}
+
+//The path part of baseURL without a trailing slash, e.g. /blog for https://example.com/blog/. Empty if the site is
+//at the root of its host.
+fn basePath(baseURL: &str) -> ~str {
+	let rest = match baseURL.find_str("://") {
+		Some(i) => baseURL.slice_from(i + "://".len()),
+		None => baseURL
+	};
+	let path = match rest.find('/') {
+		Some(i) => rest.slice_from(i),
+		None => ""
+	};
+	return path.trim_right_chars('/').to_owned();
+}
+
+//Load a vars file. Each line is "name: value", where the name may have spaces in it. Blank lines and lines starting
+//with # are skipped.
+//Values are written like values in TOML data files: "quoted" with \n, \t, \" and \\ escapes, 'quoted' as written,
+//or a [list, of, values] that is looped over with {#for item in name}. "Quoted" values and lists may span lines.
+//Anything else, such as a number or true, is used as written and can be tested with {#if name}.
+fn loadVars(varsPath: &Path) -> (Vec<(~str,~str)>, Vec<(~str, Vec<Vec<(~str,~str)>>)>) {
+	let mut vars: Vec<(~str,~str)> = Vec::new();
+	let mut lists: Vec<(~str, Vec<Vec<(~str,~str)>>)> = Vec::new();
+	let mut fileReader = BufferedReader::new(File::open(varsPath));
+	let text = fileReader.read_to_str().unwrap();
+	let fileName = varsPath.as_str().unwrap();
+	let lines: Vec<&str> = text.lines().collect();
+	let mut i = 0;
+	while i < lines.len() {
+		let lineNumber = i + 1;
+		let line = lines.get(i).trim();
+		i += 1;
+		if line.is_empty() || line.starts_with("#") {
+			continue;
+		}
+		let (name, mut value) = match line.find(':') {
+			Some(j) if j > 0 => (line.slice_to(j).trim().to_owned(), line.slice_from(j + 1).trim().to_owned()),
+			_ => {
+				reportError(format!("{}:{}: Expected \"name: value\".", fileName, lineNumber));
+				continue;
+			}
+		};
+		//A "quoted" value or list goes on until its closing quote or bracket. A value that only starts with a quote or
+		//[, such as "Fast" and simple, 'Tis the season or [beta] site, is used as written. 'Quoted' values end on their
+		//own line since apostrophes are common in text.
+		let firstLine = value.clone();
+		let nextLine = i;
+		let mut literal = false;
+		loop {
+			match valueEnd(value.as_slice()) {
+				Some(end) if end == value.len() => { break },
+				Some(_) => { literal = true },
+				None if value.starts_with("'") || i >= lines.len() => { literal = true },
+				None => {
+					value.push_char('\n');
+					value.push_str(*lines.get(i));
+					i += 1;
+					continue;
+				}
+			}
+			value = firstLine.clone();
+			i = nextLine;
+			break;
+		}
+		if literal {
+			flattenData(name, &DataText(value), &mut vars, &mut lists);
+		}
+		else {
+			flattenData(name, &parseDataScalar(value.as_slice()), &mut vars, &mut lists);
+		}
+	}
+	return (vars, lists);
+}
+
+//Where a var value ends: after the closing quote of a quoted value or the closing bracket of a list, otherwise at
+//the end of the text. None if the quote or bracket isn't closed.
+fn valueEnd(value: &str) -> Option<uint> {
+	if !(value.starts_with("\"") || value.starts_with("'") || value.starts_with("[")) {
+		return Some(value.len());
+	}
+	let mut depth = 0;
+	let mut quote = ' ';
+	let mut escaped = false;
+	for (i, c) in value.char_indices() {
+		if quote != ' ' {
+			if escaped {
+				escaped = false;
+			}
+			else if c == '\\' && quote == '"' {
+				escaped = true;
+			}
+			else if c == quote {
+				quote = ' ';
+				if depth == 0 {
+					return Some(i + 1);
+				}
+			}
+		}
+		else if c == '"' || c == '\'' {
+			quote = c;
+		}
+		else if c == '[' {
+			depth += 1;
+		}
+		else if c == ']' {
+			depth -= 1;
+			if depth == 0 {
+				return Some(i + 1);
+			}
+		}
+	}
+	return None;
+}
+
+//Read rgen.toml from the site directory into settings named by dotted paths, e.g. directories.content.
+fn loadSiteConfig(sitePath: &Path) -> Vec<(~str,~str)> {
+	let mut settings: Vec<(~str,~str)> = Vec::new();
+	let configPath = sitePath.join("rgen.toml");
+	if !configPath.exists() {
+		return settings;
+	}
+	let mut fileReader = BufferedReader::new(File::open(&configPath));
+	let value = parseTOML(fileReader.read_to_str().unwrap(), configPath.as_str().unwrap());
+	let mut lists: Vec<(~str, Vec<Vec<(~str,~str)>>)> = Vec::new();
+	flattenData("".to_owned(), &value, &mut settings, &mut lists);
+	return settings;
+}
+
+//A list setting, e.g. protect = ["CNAME"] in the [clean] table.
+fn siteSettingList(config: &Vec<(~str,~str)>, name: &str) -> Vec<~str> {
+	let mut values: Vec<~str> = Vec::new();
+	loop {
+		match lookupVar(config, name + "." + values.len().to_str()) {
+			Some(value) => { values.push(value) },
+			None => { break }
+		}
+	}
+	return values;
+}
+
+fn siteSetting(config: &Vec<(~str,~str)>, name: &str, default: &str) -> ~str {
+	return lookupVar(config, name).unwrap_or(default.to_owned());
+}
+
+//The name of a file layered over fileName, e.g. vars.de.txt for vars.txt and de.
+fn layeredFileName(fileName: &str, layer: &str) -> ~str {
+	match fileName.rfind('.') {
+		Some(i) => fileName.slice_to(i) + "." + layer + fileName.slice_from(i),
+		None => fileName + "." + layer
+	}
+}
+
+fn isLayeredFile(name: &str, fileName: &str) -> bool {
+	match fileName.rfind('.') {
+		Some(i) => name.starts_with(fileName.slice_to(i + 1)) && name.ends_with(fileName.slice_from(i)) && name.len() > fileName.len() + 1,
+		None => name.starts_with(fileName + ".")
+	}
+}
+
+//vars.txt with vars.<language>.txt layered over it. {%language} is the language being built.
+fn loadLanguageVars(pathToInclude: &Path, varsFile: &str, vars: &Vec<(~str,~str)>, lists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>, language: &str) -> (Vec<(~str,~str)>, Vec<(~str, Vec<Vec<(~str,~str)>>)>) {
+	let mut langVars = vars.clone();
+	let mut langLists = lists.clone();
+	if language.is_empty() {
+		return (langVars, langLists);
+	}
+	let langVarsPath = pathToInclude.join(layeredFileName(varsFile, language));
+	if langVarsPath.exists() {
+		layerVars(&mut langVars, &mut langLists, loadVars(&langVarsPath));
+	}
+	setVar(&mut langVars, "language".to_owned(), language.to_owned());
+	return (langVars, langLists);
+}
+
+//Put the vars and lists of a layered vars file on top of those loaded before.
+fn layerVars(vars: &mut Vec<(~str,~str)>, lists: &mut Vec<(~str, Vec<Vec<(~str,~str)>>)>, layer: (Vec<(~str,~str)>, Vec<(~str, Vec<Vec<(~str,~str)>>)>)) {
+	let (layerVars, layerLists) = layer;
+	for var in layerVars.move_iter() {
+		let (a, b) = var;
+		setVar(vars, a, b);
+	}
+	for list in layerLists.move_iter() {
+		let (name, items) = list;
+		lists.retain(|&(ref n, _)| *n != name);
+		lists.push((name, items));
+	}
+}
+
+//Vars set with environment variables: RGEN_VAR_analyticsID sets {%analyticsID}. Since names of environment
+//variables can't have dots, __ stands for one, e.g. RGEN_VAR_data__site__host sets {%data.site.host}.
+fn loadEnvironmentVars() -> Vec<(~str,~str)> {
+	let mut vars: Vec<(~str,~str)> = Vec::new();
+	for variable in os::env().iter() {
+		let (ref name, ref value) = *variable;
+		if name.starts_with("RGEN_VAR_") && name.len() > "RGEN_VAR_".len() {
+			setVar(&mut vars, name.slice_from("RGEN_VAR_".len()).replace("__", "."), value.clone());
+		}
+	}
+	return vars;
+}
+
+fn lookupVar(vars: &Vec<(~str,~str)>, name: &str) -> Option<~str> {
+	for var in vars.iter() {
+		let (ref a, ref b) = *var;
+		if a.as_slice() == name {
+			return Some(b.to_owned());
+		}
+	}
+	return None;
+}
+
+//Set a var, replacing any earlier value.
+fn setVar(vars: &mut Vec<(~str,~str)>, name: ~str, value: ~str) {
+	for var in vars.mut_iter() {
+		let (ref a, ref mut b) = *var;
+		if *a == name {
+			*b = value;
+			return;
+		}
+	}
+	vars.push((name, value));
+}
+
+//Languages listed in the languages var, "languages: en, de, ja" or the list "languages: [en, de, ja]", default
+//language first.
+//A site without the var is built once with no language.
+fn loadLanguages(vars: &Vec<(~str,~str)>, lists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>) -> Vec<~str> {
+	let mut languages: Vec<~str> = Vec::new();
+	match lookupVar(vars, "languages") {
+		Some(list) => {
+			for language in list.split(',') {
+				if language.trim() != "" {
+					languages.push(language.trim().to_owned());
+				}
+			}
+		},
+		None => { }
+	}
+	for list in lists.iter() {
+		let (ref name, ref items) = *list;
+		if name.as_slice() == "languages" {
+			for item in items.iter() {
+				match lookupVar(item, "") {
+					Some(language) => {
+						if language.trim() != "" {
+							languages.push(language.trim().to_owned());
+						}
+					},
+					None => { }
+				}
+			}
+		}
+	}
+	if languages.len() == 0 {
+		languages.push("".to_owned());
+	}
+	return languages;
+}
+
+//Language of a content file and the key shared by all of its translations.
+//about.de.md and de/about.md are both the German translation of about.md.
+fn contentLanguage(sourcePath: &str, languages: &Vec<~str>) -> (~str, ~str) {
+	for language in languages.iter() {
+		if language.is_empty() {
+			continue;
+		}
+		let dirPrefix = *language + "/";
+		if sourcePath.starts_with(dirPrefix) {
+			return (language.to_owned(), sourcePath.slice_from(dirPrefix.len()).to_owned());
+		}
+		match sourcePath.rfind('.') {
+			Some(i) => {
+				let stem = sourcePath.slice_to(i);
+				let langSuffix = "." + *language;
+				if stem.ends_with(langSuffix) {
+					return (language.to_owned(), stem.slice_to(stem.len() - langSuffix.len()) + sourcePath.slice_from(i));
+				}
+			},
+			None => { }
+		}
+	}
+	return (languages.get(0).to_owned(), sourcePath.to_owned());
+}
+
+//Output directory of a language. The default language is output at the top level.
+fn languagePrefix(language: &str, languages: &Vec<~str>) -> ~str {
+	if language.is_empty() || language == languages.get(0).as_slice() {
+		return "".to_owned();
+	}
+	return language + "/";
+}
+
+//<link rel='alternate' hreflang> tags for every published translation of a page, including the page itself.
+fn hreflangLinks(translationKey: &str, contentInfo: &Vec<ContentInfo>, hostPath: &str) -> Vec<~str> {
+	let mut returnVec: Vec<~str> = Vec::new();
+	for info in contentInfo.iter() {
+		if info.published && !info.language.is_empty() && info.translationKey.as_slice() == translationKey && !info.path.is_empty() {
+			returnVec.push(format!("<link rel='alternate' hreflang='{}' href='{}/{}'>", info.language, hostPath, info.path));
+		}
+	}
+	if returnVec.len() < 2 {
+		//No translations.
+		return Vec::new();
+	}
+	return returnVec;
+}
+
+//A value from a data file.
+enum DataValue {
+	DataText(~str),
+	DataList(Vec<DataValue>),
+	DataTable(Vec<(~str, DataValue)>)
+}
+
+//Load /data files into vars and lists named after the file, e.g. data/team.yaml becomes data.team.
+//JSON, TOML, YAML and CSV are supported. A CSV file is a list with one item per row, keyed by the header row.
+fn loadData(dataFiles: Vec<Path>) -> (Vec<(~str,~str)>, Vec<(~str, Vec<Vec<(~str,~str)>>)>) {
+	let mut dataVars: Vec<(~str,~str)> = Vec::new();
+	let mut dataLists: Vec<(~str, Vec<Vec<(~str,~str)>>)> = Vec::new();
+	for p in dataFiles.iter() {
+		let name = "data." + p.filestem_str().unwrap();
+		let mut fileReader = BufferedReader::new(File::open(p));
+		let text = fileReader.read_to_str().unwrap();
+		let value = match p.extension_str() {
+			Some("json") => {
+				match json::from_str(text) {
+					Ok(j) => jsonToData(&j),
+					Err(e) => {
+						println!("Error: Could not parse data file {}. {}", p.as_str().unwrap(), e.to_str());
+						continue;
+					}
+				}
+			},
+			Some("toml") => parseTOML(text, p.as_str().unwrap()),
+			Some("yaml") | Some("yml") => parseYAML(text, p.as_str().unwrap()),
+			Some("csv") => parseCSV(text),
+			_ => {
+				warn(format!("Data file {} is not JSON, TOML, YAML or CSV. Skipping it.", p.as_str().unwrap()));
+				continue;
+			}
+		};
+		flattenData(name, &value, &mut dataVars, &mut dataLists);
+	}
+	return (dataVars, dataLists);
+}
+
+fn joinKey(prefix: &str, key: &str) -> ~str {
+	if prefix.is_empty() {
+		return key.to_owned();
+	}
+	return prefix + "." + key;
+}
+
+//Turn a data value into vars named by dotted paths and lists of loop items.
+//List items are also available by index, e.g. {%data.team.0.name}.
+fn flattenData(name: ~str, value: &DataValue, dataVars: &mut Vec<(~str,~str)>, dataLists: &mut Vec<(~str, Vec<Vec<(~str,~str)>>)>) {
+	match *value {
+		DataText(ref text) => {
+			dataVars.push((name, text.to_owned()));
+		},
+		DataTable(ref fields) => {
+			for field in fields.iter() {
+				let (ref key, ref fieldValue) = *field;
+				flattenData(joinKey(name, *key), fieldValue, dataVars, dataLists);
+			}
+		},
+		DataList(ref values) => {
+			let mut items: Vec<Vec<(~str,~str)>> = Vec::new();
+			for (i, itemValue) in values.iter().enumerate() {
+				//Loop items use names relative to the item. {%item} is a plain value, {%item.key} a field.
+				let mut item: Vec<(~str,~str)> = Vec::new();
+				let mut itemLists: Vec<(~str, Vec<Vec<(~str,~str)>>)> = Vec::new();
+				flattenData("".to_owned(), itemValue, &mut item, &mut itemLists);
+				//Outside a loop the item is name.0, name.1... e.g. {%team.0.name}.
+				let itemName = joinKey(name, i.to_str());
+				for field in item.iter() {
+					let (ref key, ref fieldValue) = *field;
+					dataVars.push((joinKey(itemName, *key), fieldValue.clone()));
+				}
+				//Lists in an item, e.g. team.0.skills, are what {#for skill in member.skills} loops over.
+				for list in itemLists.move_iter() {
+					let (listName, listItems) = list;
+					dataLists.push((joinKey(itemName, listName), listItems));
+				}
+				items.push(item);
+			}
+			dataLists.push((name, items));
+		}
+	}
+}
+
+fn jsonToData(value: &json::Json) -> DataValue {
+	match *value {
+		json::Object(ref object) => {
+			let mut fields: Vec<(~str, DataValue)> = Vec::new();
+			for (key, fieldValue) in object.iter() {
+				fields.push((key.to_owned(), jsonToData(fieldValue)));
+			}
+			return DataTable(fields);
+		},
+		json::List(ref list) => {
+			let mut values: Vec<DataValue> = Vec::new();
+			for itemValue in list.iter() {
+				values.push(jsonToData(itemValue));
+			}
+			return DataList(values);
+		},
+		json::String(ref text) => DataText(text.to_owned()),
+		json::Number(n) => DataText(n.to_str()),
+		json::Boolean(b) => DataText(b.to_str()),
+		json::Null => DataText("".to_owned())
+	}
+}
+
+//Cut a # comment off a line. A # inside quotes or directly after other text is kept.
+fn stripComment(line: &str) -> ~str {
+	let mut quote = ' ';
+	let mut prev = ' ';
+	for (i, c) in line.char_indices() {
+		if quote != ' ' {
+			if c == quote && prev != '\\' {
+				quote = ' ';
+			}
+		}
+		else if c == '"' || c == '\'' {
+			quote = c;
+		}
+		else if c == '#' && (prev == ' ' || prev == '\t' || i == 0) {
+			return line.slice_to(i).to_owned();
+		}
+		prev = c;
+	}
+	return line.to_owned();
+}
+
+//Split text on commas that aren't inside quotes or brackets.
+fn splitItems(text: &str) -> Vec<~str> {
+	let mut items: Vec<~str> = Vec::new();
+	let mut depth = 0;
+	let mut quote = ' ';
+	let mut start = 0;
+	for (i, c) in text.char_indices() {
+		if quote != ' ' {
+			if c == quote {
+				quote = ' ';
+			}
+		}
+		else if c == '"' || c == '\'' {
+			quote = c;
+		}
+		else if c == '[' || c == '{' {
+			depth += 1;
+		}
+		else if c == ']' || c == '}' {
+			depth -= 1;
+		}
+		else if c == ',' && depth == 0 {
+			items.push(text.slice(start, i).trim().to_owned());
+			start = i + 1;
+		}
+	}
+	if text.slice_from(start).trim() != "" {
+		items.push(text.slice_from(start).trim().to_owned());
+	}
+	return items;
+}
+
+//A TOML or YAML value written on one line: a quoted string, an inline [list] or plain text such as a number.
+fn parseDataScalar(text: &str) -> DataValue {
+	let text = text.trim();
+	if text.len() >= 2 && text.starts_with("[") && text.ends_with("]") {
+		let mut values: Vec<DataValue> = Vec::new();
+		for item in splitItems(text.slice(1, text.len() - 1)).iter() {
+			values.push(parseDataScalar(*item));
+		}
+		return DataList(values);
+	}
+	if text.len() >= 2 && text.starts_with("'") && text.ends_with("'") {
+		return DataText(text.slice(1, text.len() - 1).to_owned());
+	}
+	if text.len() >= 2 && text.starts_with("\"") && text.ends_with("\"") {
+		let mut unescaped = "".to_owned();
+		let mut escaped = false;
+		for c in text.slice(1, text.len() - 1).chars() {
+			if escaped {
+				match c {
+					'n' => unescaped.push_char('\n'),
+					't' => unescaped.push_char('\t'),
+					_ => unescaped.push_char(c)
+				}
+				escaped = false;
+			}
+			else if c == '\\' {
+				escaped = true;
+			}
+			else {
+				unescaped.push_char(c);
+			}
+		}
+		return DataText(unescaped);
+	}
+	return DataText(text.to_owned());
+}
+
+//The table at path inside table, creating tables that don't exist yet. A list on the way stands for its last item.
+fn dataTableAt<'a>(table: &'a mut Vec<(~str, DataValue)>, path: &[~str]) -> &'a mut Vec<(~str, DataValue)> {
+	if path.len() == 0 {
+		return table;
+	}
+	let mut index = table.len();
+	for (i, field) in table.iter().enumerate() {
+		let (ref key, _) = *field;
+		if *key == path[0] {
+			index = i;
+		}
+	}
+	if index == table.len() {
+		table.push((path[0].clone(), DataTable(Vec::new())));
+	}
+	let &(_, ref mut fieldValue) = table.get_mut(index);
+	let isContainer = match *fieldValue {
+		DataText(_) => false,
+		_ => true
+	};
+	if !isContainer {
+		//A plain value is in the way. Replace it with a table.
+		*fieldValue = DataTable(Vec::new());
+	}
+	match *fieldValue {
+		DataList(ref mut values) => {
+			let lastIsTable = match values.last() {
+				Some(&DataTable(_)) => true,
+				_ => false
+			};
+			if !lastIsTable {
+				values.push(DataTable(Vec::new()));
+			}
+			match *values.mut_last().unwrap() {
+				DataTable(ref mut fields) => dataTableAt(fields, path.slice_from(1)),
+				_ => unreachable!()
+			}
+		},
+		DataTable(ref mut fields) => dataTableAt(fields, path.slice_from(1)),
+		DataText(_) => unreachable!()
+	}
+}
+
+fn setData(table: &mut Vec<(~str, DataValue)>, key: ~str, value: DataValue) {
+	for field in table.mut_iter() {
+		let (ref fieldKey, ref mut fieldValue) = *field;
+		if *fieldKey == key {
+			*fieldValue = value;
+			return;
+		}
+	}
+	table.push((key, value));
+}
+
+fn splitKey(key: &str) -> Vec<~str> {
+	let mut parts: Vec<~str> = Vec::new();
+	for part in key.split('.') {
+		parts.push(part.trim().trim_chars('"').to_owned());
+	}
+	return parts;
+}
+
+//A TOML value: an { inline = "table" }, an [array] whose items may be any value, or a value as in parseDataScalar.
+fn parseTOMLValue(text: &str, fileName: &str, lineNumber: uint) -> DataValue {
+	let text = text.trim();
+	if text.len() >= 2 && text.starts_with("{") && text.ends_with("}") {
+		let mut fields: Vec<(~str, DataValue)> = Vec::new();
+		for item in splitItems(text.slice(1, text.len() - 1)).iter() {
+			match item.find('=') {
+				Some(i) => {
+					let keyPath = splitKey(item.slice_to(i));
+					let table = dataTableAt(&mut fields, keyPath.as_slice().init());
+					setData(table, keyPath.last().unwrap().clone(), parseTOMLValue(item.slice_from(i + 1), fileName, lineNumber));
+				},
+				None => { warn(format!("Line {} of data file {}: {} in an inline table is not a key = value pair.", lineNumber, fileName, *item)) }
+			}
+		}
+		return DataTable(fields);
+	}
+	if text.len() >= 2 && text.starts_with("[") && text.ends_with("]") {
+		let mut values: Vec<DataValue> = Vec::new();
+		for item in splitItems(text.slice(1, text.len() - 1)).iter() {
+			values.push(parseTOMLValue(*item, fileName, lineNumber));
+		}
+		return DataList(values);
+	}
+	return parseDataScalar(text);
+}
+
+//Parse the parts of TOML that data files need: key = value pairs, [tables], [[arrays of tables]],
+//strings, numbers, booleans, inline tables and arrays, which may span lines.
+fn parseTOML(text: &str, fileName: &str) -> DataValue {
+	let mut root: Vec<(~str, DataValue)> = Vec::new();
+	let mut current: Vec<~str> = Vec::new();
+	let lines: Vec<&str> = text.lines().collect();
+	let mut next = 0;
+	while next < lines.len() {
+		let lineNumber = next;
+		let line = stripComment(*lines.get(next)).trim().to_owned();
+		next += 1;
+		if line == "".to_owned() {
+			continue;
+		}
+		if line.starts_with("[[") && line.ends_with("]]") {
+			current = splitKey(line.slice(2, line.len() - 2));
+			let parent = dataTableAt(&mut root, current.as_slice().init());
+			let name = current.last().unwrap().clone();
+			let mut found = false;
+			for field in parent.mut_iter() {
+				let (ref key, ref mut fieldValue) = *field;
+				if *key == name {
+					match *fieldValue {
+						DataList(ref mut values) => { values.push(DataTable(Vec::new())); found = true; },
+						_ => { }
+					}
+				}
+			}
+			if !found {
+				setData(parent, name, DataList(vec!(DataTable(Vec::new()))));
+			}
+		}
+		else if line.starts_with("[") && line.ends_with("]") {
+			current = splitKey(line.slice(1, line.len() - 1));
+			dataTableAt(&mut root, current.as_slice());
+		}
+		else {
+			match line.find('=') {
+				Some(i) => {
+					let mut keyPath = current.clone();
+					keyPath.push_all(splitKey(line.slice_to(i)).as_slice());
+					//An array goes on until its closing bracket.
+					let mut value = line.slice_from(i + 1).trim().to_owned();
+					while valueEnd(value.as_slice()).is_none() && next < lines.len() {
+						value.push_str(" " + stripComment(*lines.get(next)).trim());
+						next += 1;
+					}
+					if valueEnd(value.as_slice()).is_none() {
+						warn(format!("Line {} of data file {}: the value of {} is never closed.", lineNumber + 1, fileName, keyPath.connect(".")));
+					}
+					let table = dataTableAt(&mut root, keyPath.as_slice().init());
+					setData(table, keyPath.last().unwrap().clone(), parseTOMLValue(value, fileName, lineNumber + 1));
+				},
+				None => { warn(format!("Line {} of data file {} is not a key = value pair.", lineNumber + 1, fileName)) }
+			}
+		}
+	}
+	return DataTable(root);
+}
+
+//Split "key: value" or "key:" into key and value.
+fn yamlKeyValue(line: &str) -> Option<(~str, ~str)> {
+	if line.starts_with("\"") || line.starts_with("'") || line.starts_with("[") {
+		return None;
+	}
+	match line.find_str(": ") {
+		Some(i) => { return Some((line.slice_to(i).trim().to_owned(), line.slice_from(i + 2).trim().to_owned())) },
+		None => { }
+	}
+	if line.ends_with(":") {
+		return Some((line.slice_to(line.len() - 1).trim().to_owned(), "".to_owned()));
+	}
+	return None;
+}
+
+//Parse the block style parts of YAML that data files need: nested mappings and "- " lists by indentation,
+//| and > multi-line strings, and one line values as in parseDataScalar.
+fn parseYAML(text: &str, fileName: &str) -> DataValue {
+	//(indentation, trimmed line) of every line with content.
+	let mut lines: Vec<(uint, ~str)> = Vec::new();
+	for rawLine in text.lines() {
+		let line = stripComment(rawLine);
+		if line.trim() == "" || line.trim() == "---" {
+			continue;
+		}
+		lines.push((line.len() - line.trim_left().len(), line.trim().to_owned()));
+	}
+	if lines.len() == 0 {
+		return DataTable(Vec::new());
+	}
+	let mut pos = 0;
+	let indent = lines.get(0).val0();
+	return parseYAMLBlock(&mut lines, &mut pos, indent, fileName);
+}
+
+//Parse the lines from pos that are indented by indent: a list if they start with "- ", a mapping otherwise.
+fn parseYAMLBlock(lines: &mut Vec<(uint, ~str)>, pos: &mut uint, indent: uint, fileName: &str) -> DataValue {
+	let first = lines.get(*pos).val1();
+	if first.starts_with("- ") || first == "-".to_owned() {
+		let mut values: Vec<DataValue> = Vec::new();
+		while *pos < lines.len() {
+			let (lineIndent, line) = lines.get(*pos).clone();
+			if lineIndent != indent || !(line.starts_with("- ") || line == "-".to_owned()) {
+				break;
+			}
+			let item = line.slice_from(1).trim().to_owned();
+			if item == "".to_owned() {
+				//The item is the block on the following lines.
+				*pos += 1;
+				if *pos < lines.len() && lines.get(*pos).val0() > indent {
+					let itemIndent = lines.get(*pos).val0();
+					values.push(parseYAMLBlock(lines, pos, itemIndent, fileName));
+				}
+				else {
+					values.push(DataText("".to_owned()));
+				}
+			}
+			else if yamlKeyValue(item).is_some() {
+				//A mapping that starts on the same line as the "- ".
+				let itemIndent = lineIndent + line.len() - item.len();
+				*lines.get_mut(*pos) = (itemIndent, item);
+				values.push(parseYAMLBlock(lines, pos, itemIndent, fileName));
+			}
+			else {
+				values.push(parseDataScalar(item));
+				*pos += 1;
+			}
+		}
+		return DataList(values);
+	}
+	let mut fields: Vec<(~str, DataValue)> = Vec::new();
+	while *pos < lines.len() {
+		let (lineIndent, line) = lines.get(*pos).clone();
+		if lineIndent < indent {
+			break;
+		}
+		*pos += 1;
+		if lineIndent > indent {
+			warn(format!("Unexpected indentation in data file {}: {}", fileName, line));
+			continue;
+		}
+		match yamlKeyValue(line) {
+			Some((key, value)) => {
+				if value == "|".to_owned() || value == ">".to_owned() {
+					//Multi-line string from the more indented lines that follow.
+					let separator = if value == "|".to_owned() { "\n" } else { " " };
+					let mut text = "".to_owned();
+					while *pos < lines.len() && lines.get(*pos).val0() > indent {
+						if text != "".to_owned() {
+							text = text + separator;
+						}
+						text = text + lines.get(*pos).val1();
+						*pos += 1;
+					}
+					fields.push((key, DataText(text)));
+				}
+				else if value != "".to_owned() {
+					fields.push((key, parseDataScalar(value)));
+				}
+				else if *pos < lines.len() && (lines.get(*pos).val0() > indent || (lines.get(*pos).val0() == indent && lines.get(*pos).val1().starts_with("- "))) {
+					let nestedIndent = lines.get(*pos).val0();
+					fields.push((key, parseYAMLBlock(lines, pos, nestedIndent, fileName)));
+				}
+				else {
+					fields.push((key, DataText("".to_owned())));
+				}
+			},
+			None => { warn(format!("Expected \"key: value\" in data file {}: {}", fileName, line)) }
+		}
+	}
+	return DataTable(fields);
+}
+
+//Rows of a CSV file. Quoted fields may contain commas, newlines and "" for a quote.
+fn parseCSV(text: &str) -> DataValue {
+	let mut rows: Vec<Vec<~str>> = Vec::new();
+	let mut row: Vec<~str> = Vec::new();
+	let mut field = "".to_owned();
+	let mut quoted = false;
+	let mut chars = text.chars().peekable();
+	loop {
+		let c = match chars.next() {
+			Some(c) => c,
+			None => { break }
+		};
+		if quoted {
+			if c == '"' {
+				if chars.peek() == Some(&'"') {
+					field.push_char('"');
+					chars.next();
+				}
+				else {
+					quoted = false;
+				}
+			}
+			else {
+				field.push_char(c);
+			}
+		}
+		else {
+			match c {
+				'"' => { quoted = true },
+				',' => {
+					row.push(field.trim().to_owned());
+					field = "".to_owned();
+				},
+				'\n' => {
+					row.push(field.trim().to_owned());
+					field = "".to_owned();
+					rows.push(row);
+					row = Vec::new();
+				},
+				'\r' => { },
+				_ => { field.push_char(c) }
+			}
+		}
+	}
+	if field != "".to_owned() || row.len() > 0 {
+		row.push(field.trim().to_owned());
+		rows.push(row);
+	}
+	let mut values: Vec<DataValue> = Vec::new();
+	if rows.len() == 0 {
+		return DataList(values);
+	}
+	let header = rows.get(0).clone();
+	for row in rows.iter().skip(1) {
+		if row.len() == 1 && row.get(0).is_empty() {
+			//Blank line
+			continue;
+		}
+		let mut fields: Vec<(~str, DataValue)> = Vec::new();
+		for (i, key) in header.iter().enumerate() {
+			let value = if i < row.len() { row.get(i).clone() } else { "".to_owned() };
+			fields.push((key.clone(), DataText(value)));
+		}
+		values.push(DataTable(fields));
+	}
+	return DataList(values);
+}
+
+//Includes are loaded once. Vars and links in them are replaced when each page is built, like those in templates, so
+//that the page's config values can override vars.
+fn loadIncludes(includeFiles: Vec<Path>) -> Vec<(~str,~str)> {
+	let mut returnVec: Vec<(~str,~str)> = Vec::new();
+	for p in includeFiles.iter() {
+		let fileName = p.filestem_str().unwrap().to_owned();
+		let mut fileReader = BufferedReader::new(File::open(p));
+		let fileContent = fileReader.read_to_str().unwrap().to_owned();
+		//println!("{}", fileContent);
+		returnVec.push((fileName, fileContent));
+	}
+	return returnVec;
+}
+
+//Config values of a content file that are needed before any content is loaded.
+struct ContentInfo {
+	file: Path,
+	language: ~str,
+	translationKey: ~str, //Shared by all translations of a page
+	linkName: ~str,
+	linkPath: ~str, //What {$linkName} is replaced with
+	path: ~str, //Output path, including the language prefix
+	published: bool,
+	menus: Vec<~str>, //Generated menus the page is listed in
+	weight: int, //Menu entries are sorted by weight, lightest first
+	parent: ~str //linkName of the page this one is listed under
+}
+
+//This method is slow. Fix it. 
+fn scanContent(contentFiles: &Vec<(Path, SourceFile)>, pathToContent: &Path, languages: &Vec<~str>, now: &str, options: &BuildOptions, hostPath: &str) -> Vec<ContentInfo> {
+	let mut returnVec: Vec<ContentInfo> = Vec::new();
+	for &(ref p, ref source) in contentFiles.iter() {
+		let mut linkName = "".to_owned();
+		let mut linkPath = "".to_owned();
+		let mut draft = false;
+		let mut publishDate = "".to_owned();
+		let mut expiryDate = "".to_owned();
+		let mut menus: Vec<~str> = Vec::new();
+		let mut weight = 0;
+		let mut parent = "".to_owned();
+		for item in sectionItems(source, "config").iter() {
+			let st = item.text.as_slice();
+			match st.split(':').next().unwrap().trim() {
+				"linkName" => {
+					linkName = configValue(st);
+				},
+				"path" => {
+					linkPath = configValue(st);
+				},
+				"draft" => {
+					draft = configValue(st) == "true".to_owned();
+				},
+				"publishDate" => {
+					publishDate = configValue(st);
+				},
+				"expiryDate" => {
+					expiryDate = configValue(st);
+				},
+				"menu" => {
+					for menu in configValue(st).split(',') {
+						if menu.trim() != "" {
+							menus.push(menu.trim().to_owned());
+						}
+					}
+				},
+				"weight" => {
+					match from_str::<int>(configValue(st)) {
+						Some(n) => { weight = n },
+						None => { warn(format!("weight for content file {} is not a number.", p.filename_str().unwrap())) }
+					}
+				},
+				"parent" => {
+					parent = configValue(st);
+				},
+				_ => { }
+			}
+		}
+		let published = exclusionReason(draft, publishDate.as_slice(), expiryDate.as_slice(), now, options).is_none();
+		if published && (linkName == "".to_owned() || linkPath == "".to_owned()) {
+			warn(format!("linkName or linkPath for content file {} is not set.", p.filename_str().unwrap()));
+		}
+		let sourcePath = match p.path_relative_from(pathToContent) {
+			Some(rel) => rel.as_str().unwrap().to_owned(),
+			None => p.as_str().unwrap().to_owned()
+		};
+		let (language, translationKey) = contentLanguage(sourcePath.as_slice(), languages);
+		let prefix = languagePrefix(language.as_slice(), languages);
+		//Links are root-relative so that they work from pages in any directory, e.g. /about.html or /de/about.html.
+		//hostPath is where the site is on its host, e.g. /docs for /docs/about.html.
+		let link = if linkPath.is_empty() || linkPath.contains("://") { linkPath.clone() } else { format!("{}/{}{}", hostPath, prefix, linkPath.trim_left_chars('/')) };
+		returnVec.push(ContentInfo {
+			file: p.clone(),
+			language: language,
+			translationKey: translationKey,
+			linkName: linkName,
+			linkPath: link,
+			path: if linkPath.is_empty() { linkPath.clone() } else { prefix + linkPath },
+			published: published,
+			menus: menus,
+			weight: weight,
+			parent: parent
+		});
+	}
+	return returnVec;
+}
+
+//(linkName, path) of the published pages in a language.
+fn loadLinks(contentInfo: &Vec<ContentInfo>, language: &str) -> Vec<(~str,~str)> {
+	let mut returnVec: Vec<(~str,~str)> = Vec::new();
+	for info in contentInfo.iter() {
+		//Unpublished pages can't be linked to.
+		if info.published && info.language.as_slice() == language && !info.linkName.is_empty() && !info.linkPath.is_empty() {
+			returnVec.push((info.linkName.clone(), info.linkPath.clone()));
+		}
+	}
+	return returnVec;
+}
+
+//Links to css and js files in /resources. They are root-relative so that they work from pages in any directory,
+//such as a language's pages or later pages of a listing. hostPath is where the site is on its host, e.g. /blog.
+fn cssTag(hostPath: &str, file: &str) -> ~str {
+	return format!("<link rel='stylesheet' type='text/css' href='{}/resources/css/{}'>", hostPath, file);
+}
+
+fn jsTag(hostPath: &str, file: &str) -> ~str {
+	return format!("<script type='text/javascript' src='{}/resources/js/{}'></script>", hostPath, file);
+}
+
+fn loadGlobalCSSJS(globalPath: Path, hostPath: &str) -> Vec<~str> {
+	let mut returnVec: Vec<~str> = Vec::new();
+	let mut fileReader = BufferedReader::new(File::open(&globalPath));
+	let line1 = fileReader.read_line().unwrap();
+	let css = line1.trim() == "css";
+	let mut js = line1.trim() == "js";
+	while css {
+		let nextLine = fileReader.read_line();
+		match nextLine {
+			Ok(tex) => {
+				let texOwned = tex.trim();
+				if texOwned == "js" {
+					js = true;
+					break;
+				}
+				else {
+					returnVec.push(cssTag(hostPath, texOwned));
+				}
+			},
+			Err(_) => { break }
+		}
+	}
+	while js {
+		let nextLine = fileReader.read_line();
+		match nextLine {
+			Ok(tex) => {
+				let texOwned = tex.trim();
+				returnVec.push(jsTag(hostPath, texOwned));
+			},
+			Err(_) => { break }
+		}
+	}
+	/*println!("Global includes:");
+	for elem in returnVec.iter() {
+		println!("{}", elem);
+	}*/
+	return returnVec;
+}
+
+//Where something is in a content or template file. Lines and columns start at 1.
+struct Span {
+	line: uint,
+	column: uint
+}
+
+//A line of a section and the lines indented under it.
+struct Node {
+	text: ~str, //The line without its indentation
+	span: Span,
+	children: Vec<Node>
+}
+
+impl Clone for Node {
+	fn clone(&self) -> Node {
+		let myNode = Node { text: self.text.clone(), span: self.span, children: self.children.clone() };
+		return myNode;
+	}
+}
+
+//A section at the top of a content or template file, e.g. config or blocks.
+struct Section {
+	name: ~str,
+	argument: ~str, //The rest of the section's line, e.g. the template named by inherit
+	span: Span,
+	items: Vec<Node>
+}
+
+impl Clone for Section {
+	fn clone(&self) -> Section {
+		let mySection = Section { name: self.name.clone(), argument: self.argument.clone(), span: self.span, items: self.items.clone() };
+		return mySection;
+	}
+}
+
+//A parsed content or template file. The body is the page content or the template's HTML.
+struct SourceFile {
+	sections: Vec<Section>,
+	body: ~str
+}
+
+impl Clone for SourceFile {
+	fn clone(&self) -> SourceFile {
+		let mySource = SourceFile { sections: self.sections.clone(), body: self.body.clone() };
+		return mySource;
+	}
+}
+
+struct ParseError {
+	span: Span,
+	message: ~str
+}
+
+//A line of a section split into its indentation and text.
+struct SourceLine {
+	number: uint,
+	indent: ~str,
+	text: ~str
+}
+
+impl SourceLine {
+	fn span(&self) -> Span {
+		return Span { line: self.number, column: self.indent.char_len() + 1 };
+	}
+}
+
+//Sections a file may have and how many levels of lines each has under it.
+//Content blocks have block names, then part names, then the part's text. Template blocks have names, then HTML.
+//Regions have the region's name, then its text.
+static CONTENT_SECTIONS: &'static [(&'static str, uint)] = &[("config", 1), ("css", 1), ("js", 1), ("blocks", 3), ("regions", 2)];
+static TEMPLATE_SECTIONS: &'static [(&'static str, uint)] = &[("inherit", 0), ("css", 1), ("js", 1), ("blocks", 2), ("regions", 2)];
+
+//Parse a content or template file. It starts with sections: a section name on an unindented line and then the lines
+//indented under it. Any indentation works as long as the lines at a level are indented the same way.
+//The body starts at the first unindented line that isn't a section name, or after a blank line in blocks.
+fn parseSourceFile(text: &str, sectionNames: &[(&str, uint)]) -> Result<SourceFile, ParseError> {
+	let mut source = SourceFile { sections: Vec::new(), body: "".to_owned() };
+	//Lines of the current section and how deep they may go.
+	let mut lines: Vec<SourceLine> = Vec::new();
+	let mut depth = 0;
+	let mut offset = 0;
+	let mut bodyStart = text.len();
+	for (i, rawLine) in text.split('\n').enumerate() {
+		let lineStart = offset;
+		offset += rawLine.len() + 1;
+		let line = rawLine.trim_right();
+		let lineText = line.trim_left();
+		let indent = line.slice_to(line.len() - lineText.len());
+		let inBlocks = source.sections.last().map_or(false, |section| section.name.as_slice() == "blocks");
+		if lineText.is_empty() {
+			if inBlocks {
+				bodyStart = if offset < text.len() { offset } else { text.len() };
+				break;
+			}
+			continue;
+		}
+		if !indent.is_empty() {
+			if source.sections.len() == 0 {
+				bodyStart = lineStart;
+				break;
+			}
+			lines.push(SourceLine { number: i + 1, indent: indent.to_owned(), text: lineText.to_owned() });
+			continue;
+		}
+		let mut words = lineText.splitn(' ', 1);
+		let name = words.next().unwrap();
+		let argument = words.next().unwrap_or("").trim();
+		let mut sectionDepth: Option<uint> = None;
+		for &(sectionName, d) in sectionNames.iter() {
+			if sectionName == name && (name == "inherit" || argument.is_empty()) {
+				sectionDepth = Some(d);
+			}
+		}
+		match sectionDepth {
+			Some(d) => {
+				match finishSection(&mut source, &lines, depth) {
+					Ok(_) => { },
+					Err(e) => { return Err(e) }
+				}
+				if name == "inherit" && argument.is_empty() {
+					return Err(ParseError { span: Span { line: i + 1, column: 1 }, message: "inherit needs the name of a template.".to_owned() });
+				}
+				source.sections.push(Section { name: name.to_owned(), argument: argument.to_owned(), span: Span { line: i + 1, column: 1 }, items: Vec::new() });
+				lines = Vec::new();
+				depth = d;
+			},
+			None => {
+				bodyStart = lineStart;
+				break;
+			}
+		}
+	}
+	match finishSection(&mut source, &lines, depth) {
+		Ok(_) => { },
+		Err(e) => { return Err(e) }
+	}
+	source.body = text.slice_from(bodyStart).to_owned();
+	return Ok(source);
+}
+
+//Parse the lines of the last section of source into its items.
+fn finishSection(source: &mut SourceFile, lines: &Vec<SourceLine>, depth: uint) -> Result<(), ParseError> {
+	let raw = match source.sections.last() {
+		Some(section) => section.name.as_slice() == "blocks" || section.name.as_slice() == "regions",
+		None => { return Ok(()) }
+	};
+	let mut pos = 0;
+	match parseNodes(lines, &mut pos, "", depth, raw) {
+		Ok(items) => {
+			source.sections.mut_last().unwrap().items = items;
+			return Ok(());
+		},
+		Err(e) => { return Err(e) }
+	}
+}
+
+//Parse the lines indented under parentIndent, up to depth levels deep. With raw the deepest level is text such as
+//HTML, whose lines keep any indentation beyond that of its first line.
+fn parseNodes(lines: &Vec<SourceLine>, pos: &mut uint, parentIndent: &str, depth: uint, raw: bool) -> Result<Vec<Node>, ParseError> {
+	let mut nodes: Vec<Node> = Vec::new();
+	let mut levelIndent = "".to_owned();
+	while *pos < lines.len() {
+		let line = lines.get(*pos);
+		if line.indent.len() <= parentIndent.len() || !line.indent.starts_with(parentIndent) {
+			//This line belongs to a level above this one.
+			break;
+		}
+		if depth == 0 {
+			return Err(ParseError { span: line.span(), message: "This line is indented further than it can be.".to_owned() });
+		}
+		if nodes.len() == 0 {
+			levelIndent = line.indent.clone();
+		}
+		if depth == 1 && raw {
+			if !line.indent.starts_with(levelIndent) {
+				return Err(ParseError { span: line.span(), message: format!("Indentation doesn't match line {}.", nodes.get(0).span.line) });
+			}
+			nodes.push(Node { text: line.indent.slice_from(levelIndent.len()) + line.text, span: line.span(), children: Vec::new() });
+			*pos += 1;
+			continue;
+		}
+		if line.indent != levelIndent {
+			return Err(ParseError { span: line.span(), message: format!("Indentation doesn't match line {}.", nodes.last().unwrap().span.line) });
+		}
+		*pos += 1;
+		let children = match parseNodes(lines, pos, levelIndent, depth - 1, raw) {
+			Ok(children) => children,
+			Err(e) => { return Err(e) }
+		};
+		nodes.push(Node { text: line.text.clone(), span: line.span(), children: children });
+	}
+	return Ok(nodes);
+}
+
+fn parseContentFile(text: &str) -> Result<SourceFile, ParseError> {
+	let source = match parseSourceFile(text, CONTENT_SECTIONS) {
+		Ok(source) => source,
+		Err(e) => { return Err(e) }
+	};
+	if source.sections.len() == 0 || source.sections.get(0).name.as_slice() != "config" {
+		return Err(ParseError { span: Span { line: 1, column: 1 }, message: "Content files start with config.".to_owned() });
+	}
+	for section in source.sections.iter() {
+		if section.name.as_slice() == "config" {
+			for item in section.items.iter() {
+				if !item.text.contains_char(':') {
+					return Err(ParseError { span: item.span, message: "Expected \"key: value\".".to_owned() });
+				}
+			}
+		}
+	}
+	return checkRegions(source);
+}
+
+fn parseTemplateFile(text: &str) -> Result<SourceFile, ParseError> {
+	match parseSourceFile(text, TEMPLATE_SECTIONS) {
+		Ok(source) => { return checkRegions(source) },
+		Err(e) => { return Err(e) }
+	}
+}
+
+//Each line of a regions section is a region's name, optionally followed by append or prepend.
+fn checkRegions(source: SourceFile) -> Result<SourceFile, ParseError> {
+	for item in sectionItems(&source, "regions").iter() {
+		let words: Vec<&str> = item.text.words().collect();
+		if words.len() > 2 || (words.len() == 2 && *words.get(1) != "append" && *words.get(1) != "prepend") {
+			return Err(ParseError { span: item.span, message: "Expected \"name\", \"name append\" or \"name prepend\".".to_owned() });
+		}
+	}
+	return Ok(source);
+}
+
+//Read and parse content or template files. Files with syntax errors are left out.
+fn parseFiles(files: &Vec<Path>, parse: fn(&str) -> Result<SourceFile, ParseError>) -> Vec<(Path, SourceFile)> {
+	let mut parsed: Vec<(Path, SourceFile)> = Vec::new();
+	for p in files.iter() {
+		let mut fileReader = BufferedReader::new(File::open(p));
+		let text = fileReader.read_to_str().unwrap();
+		match parse(text) {
+			Ok(source) => { parsed.push((p.clone(), source)) },
+			Err(e) => { reportError(format!("{}:{}:{}: {}", p.as_str().unwrap(), e.span.line, e.span.column, e.message)) }
+		}
+	}
+	return parsed;
+}
+
+//The items of every section of source with this name.
+fn sectionItems<'a>(source: &'a SourceFile, name: &str) -> Vec<&'a Node> {
+	let mut items: Vec<&'a Node> = Vec::new();
+	for section in source.sections.iter() {
+		if section.name.as_slice() == name {
+			for item in section.items.iter() {
+				items.push(item);
+			}
+		}
+	}
+	return items;
+}
+
+//The text of the lines under a node, e.g. the HTML of a template block.
+fn nodeText(nodes: &Vec<Node>) -> ~str {
+	let lines: Vec<~str> = nodes.iter().map(|node| node.text.clone()).collect();
+	return lines.connect("\n");
+}
+
+//Replace includes, vars and links in a line of a section if it has any. Without escape var values are put in as
+//they are, for config values that are escaped when they are used.
+fn expandLine(mut line: ~str, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, includes: &Vec<(~str,~str)>, escape: bool) -> ~str {
+	if line.contains_char('{') && line.contains_char('}') {
+		//Check if there are includes and insert them. Their vars are replaced with the rest of the line's.
+		if line.contains("{.") {
+			line = insertIncludes(line, includes);
+		}
+		//Check if there are variables/links and replace them.
+		if line.contains("{$") || line.contains("{%") {
+			line = replaceVars(line, vars, internalLinks, escape);
+		}
+	}
+	return line;
+}
+
+//A change to a named region, which a template marks with {#region name}default content{/region}.
+//Templates that inherit from it and pages change regions in their regions section.
+struct Region {
+	name: ~str,
+	mode: ~str, //replace, append or prepend
+	content: ~str
+}
+
+impl Clone for Region {
+	fn clone(&self) -> Region {
+		let myRegion = Region { name: self.name.clone(), mode: self.mode.clone(), content: self.content.clone() };
+		return myRegion;
+	}
+}
+
+fn loadRegions(source: &SourceFile, includes: &Vec<(~str,~str)>) -> Vec<Region> {
+	let mut regions: Vec<Region> = Vec::new();
+	for item in sectionItems(source, "regions").iter() {
+		let mut words = item.text.words();
+		let name = words.next().unwrap().to_owned();
+		let mode = words.next().unwrap_or("replace").to_owned();
+		regions.push(Region { name: name, mode: mode, content: insertIncludes(nodeText(&item.children), includes) });
+	}
+	return regions;
+}
+
+//Replace, append to or prepend to the content of every {#region name} in text. The region tags are kept so that
+//later changes find them too.
+fn applyRegion(text: ~str, region: &Region) -> ~str {
+	let open = "{#region " + region.name + "}";
+	let mut result = "".to_owned();
+	let mut rest = text.as_slice();
+	loop {
+		let start = match rest.find_str(open) {
+			Some(i) => i + open.len(),
+			None => { break }
+		};
+		let end = match findClosingTag(rest.slice_from(start), "{#region ", "{/region}") {
+			Some(i) => start + i,
+			None => { break }
+		};
+		result.push_str(rest.slice_to(start));
+		match region.mode.as_slice() {
+			"append" => {
+				result.push_str(rest.slice(start, end));
+				result.push_str(region.content);
+			},
+			"prepend" => {
+				result.push_str(region.content);
+				result.push_str(rest.slice(start, end));
+			},
+			_ => { result.push_str(region.content) }
+		}
+		rest = rest.slice_from(end);
+	}
+	result.push_str(rest);
+	return result;
+}
+
+//Remove {#region name} and {/region} once every change to the regions has been made.
+fn removeRegionTags(text: ~str) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text.as_slice();
+	loop {
+		let start = match rest.find_str("{#region ") {
+			Some(i) => i,
+			None => { break }
+		};
+		result.push_str(rest.slice_to(start));
+		match rest.slice_from(start).find('}') {
+			Some(i) => { rest = rest.slice_from(start + i + 1) },
+			None => {
+				rest = rest.slice_from(start);
+				break;
+			}
+		}
+	}
+	result.push_str(rest);
+	return result.replace("{/region}", "");
+}
+
+struct Template {
+	name: ~str,
+	inherit: ~str,
+	headData: Vec<~str>,
+	blockTemplates: Vec<(~str,~str)>,
+	content: ~str,
+	regions: Vec<Region> //Changes to regions of the templates this one inherits from
+}
+
+impl Clone for Template {
+	fn clone(&self) -> Template {
+		let myTemplate = Template {name: self.name.clone(), inherit: self.inherit.clone(), headData: self.headData.clone(), blockTemplates: self.blockTemplates.clone(), content: self.content.clone(), regions: self.regions.clone()};
+		return myTemplate;
+	}
+}
+
+//Templates are loaded once per language with only their includes inserted. Vars and links in them, including in
+//blocks and css and js lines, are replaced when each page is built so that the page's config values can override vars.
+fn loadTemplates(templateFiles: &Vec<(Path, SourceFile)>, includes: &Vec<(~str,~str)>, hostPath: &str) -> Vec<Template> {
+	let mut returnVec: Vec<Template> = Vec::new();
+	for &(ref file, ref source) in templateFiles.iter() {
+		let mut myTemplate = Template {name: file.filestem_str().unwrap().to_owned(), inherit: "".to_owned(), headData: Vec::new(), blockTemplates: Vec::new(), content: "".to_owned(), regions: loadRegions(source, includes)};
+		for section in source.sections.iter() {
+			match section.name.as_slice() {
+				"inherit" => {
+					myTemplate.inherit = section.argument.clone();
+				},
+				"css" => {
+					for item in section.items.iter() {
+						myTemplate.headData.push(cssTag(hostPath, insertIncludes(item.text.clone(), includes)));
+					}
+				},
+				"js" => {
+					for item in section.items.iter() {
+						myTemplate.headData.push(jsTag(hostPath, insertIncludes(item.text.clone(), includes)));
+					}
+				},
+				"blocks" => {
+					for block in section.items.iter() {
+						let blockContent = insertIncludes(nodeText(&block.children), includes);
+						myTemplate.blockTemplates.push((block.text.clone(), blockContent));
+					}
+				},
+				_ => { }
+			}
+		}
+		myTemplate.content = insertIncludes(source.body.clone(), includes);
+		returnVec.push(myTemplate);
+	}
+	return returnVec;
+}
+
+fn replaceVars(mut text: ~str, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, escape: bool) -> ~str {
+	text = substituteVars(text, |name| lookupVar(vars, name), escape);
+	for link in internalLinks.iter() {
+		let (ref a, ref b) = *link;
+		text = text.replace("{$" + *a + "}", *b);
+	}
+	return text
+}
+
+fn insertIncludes(mut text: ~str, includes: &Vec<(~str,~str)>) -> ~str {
+	for include in includes.iter() {
+		let (ref a, ref b) = *include;
+		text = text.replace("{." + *a + "}", *b);
+	}
+	return text;
+}
+
+//Replace the {%name} placeholders in text whose value lookup knows. Others are left for later.
+//A value can be passed through filters, e.g. {%title | upper | truncate(20)}. With escape values are escaped for
+//where they are, text or an attribute value, unless a filter is raw or markdown.
+fn substituteVars(text: &str, lookup: |&str| -> Option<~str>, escape: bool) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text;
+	loop {
+		let start = match rest.find_str("{%") {
+			Some(i) => i,
+			None => { break }
+		};
+		let end = match findClosingTag(rest.slice_from(start + 1), "{", "}") {
+			Some(i) => start + 1 + i,
+			None => { break }
+		};
+		let mut filters = rest.slice(start + 2, end).split('|');
+		let name = filters.next().unwrap().trim();
+		match lookup(name) {
+			Some(found) => {
+				result.push_str(rest.slice_to(start));
+				let mut value = found;
+				let mut escapeValue = escape;
+				for filter in filters {
+					let (filterName, argument) = parseFilter(filter);
+					match filterName.as_slice() {
+						"raw" => { escapeValue = false },
+						"markdown" => {
+							value = if value.trim().contains_char('\n') { renderMarkdown(value.trim()) } else { renderInline(value.trim()) };
+							escapeValue = false;
+						},
+						_ => { value = applyFilter(value, filterName.as_slice(), argument.as_slice()) }
+					}
+				}
+				if escapeValue {
+					//After a < with no > since, the placeholder is inside a tag.
+					value = if result.rfind('<') > result.rfind('>') { escapeHTML(value) } else { escapeText(value) };
+				}
+				result.push_str(value);
+			},
+			None => { result.push_str(rest.slice_to(end + 1)) }
+		}
+		rest = rest.slice_from(end + 1);
+	}
+	result.push_str(rest);
+	return result;
+}
+
+//Split a filter such as truncate(80) or date("%d %B %Y") into its name and argument.
+fn parseFilter(filter: &str) -> (~str, ~str) {
+	let filter = filter.trim();
+	match filter.find('(') {
+		Some(i) if filter.ends_with(")") => {
+			let argument = filter.slice(i + 1, filter.len() - 1).trim();
+			let quoted = argument.len() >= 2 && ((argument.starts_with("\"") && argument.ends_with("\"")) || (argument.starts_with("'") && argument.ends_with("'")));
+			let argument = if quoted { argument.slice(1, argument.len() - 1) } else { argument };
+			return (filter.slice_to(i).trim().to_owned(), argument.to_owned());
+		},
+		_ => { return (filter.to_owned(), "".to_owned()) }
+	}
+}
+
+fn applyFilter(value: ~str, filter: &str, argument: &str) -> ~str {
+	match filter {
+		"upper" => { return value.chars().map(|c| c.to_uppercase()).collect() },
+		"lower" => { return value.chars().map(|c| c.to_lowercase()).collect() },
+		"truncate" => {
+			match from_str::<uint>(argument) {
+				Some(n) => {
+					if value.char_len() <= n {
+						return value;
+					}
+					return value.slice_chars(0, n).trim_right() + "...";
+				},
+				None => {
+					warn(format!("truncate needs a number of characters, not \"{}\".", argument));
+					return value;
+				}
+			}
+		},
+		"date" => {
+			let format = if argument.is_empty() { "%Y-%m-%d" } else { argument };
+			match formatDate(value, format) {
+				Some(date) => { return date },
+				None => {
+					warn(format!("{} is not a date. Dates are written as YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS.", value));
+					return value;
+				}
+			}
+		},
+		"slugify" => { return slugify(value) },
+		"urlencode" => { return urlEncode(value) },
+		_ => {
+			warn(format!("Unknown filter {}.", filter));
+			return value;
+		}
+	}
+}
+
+fn formatDate(value: &str, format: &str) -> Option<~str> {
+	for pattern in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d"].iter() {
+		match time::strptime(value.trim(), *pattern) {
+			Ok(tm) => { return Some(tm.strftime(format)) },
+			Err(_) => { }
+		}
+	}
+	return None;
+}
+
+//Percent-encode everything but letters, digits and -_.~ for use in a URL.
+fn urlEncode(text: &str) -> ~str {
+	let mut encoded = "".to_owned();
+	for byte in text.bytes() {
+		let c = byte as char;
+		if (byte < 128 && c.is_alphanumeric()) || c == '-' || c == '_' || c == '.' || c == '~' {
+			encoded.push_char(c);
+		}
+		else {
+			encoded.push_str(format!("%{:02X}", byte));
+		}
+	}
+	return encoded;
+}
+
+struct Shortcode {
+	name: ~str,
+	content: ~str,
+	beforeMarkdown: bool //Expanded before mdToHTML, so its content is Markdown
+}
+
+//Load include/shortcodes. A shortcode is named after its file. If the first line of the file is "expand: before"
+//it is expanded before Markdown is rendered, otherwise after. {%arg.name} is replaced with the argument called name
+//and {%inner} with the content between {{< name >}} and {{< /name >}}.
+fn loadShortcodes(shortcodeFiles: Vec<Path>) -> Vec<Shortcode> {
+	let mut returnVec: Vec<Shortcode> = Vec::new();
+	for p in shortcodeFiles.iter() {
+		let mut fileReader = BufferedReader::new(File::open(p));
+		let fileContent = fileReader.read_to_str().unwrap();
+		let firstLine = fileContent.lines().next().unwrap_or("").trim().to_owned();
+		let mut shortcode = Shortcode { name: p.filestem_str().unwrap().to_owned(), content: fileContent.clone(), beforeMarkdown: false };
+		if firstLine.starts_with("expand:") {
+			shortcode.beforeMarkdown = configValue(firstLine) == "before".to_owned();
+			shortcode.content = match fileContent.find('\n') {
+				Some(i) => fileContent.slice_from(i + 1).to_owned(),
+				None => "".to_owned()
+			};
+		}
+		returnVec.push(shortcode);
+	}
+	return returnVec;
+}
+
+fn expandPageShortcodes(pages: &mut Vec<Page>, shortcodes: &Vec<Shortcode>, beforeMarkdown: bool) {
+	for page in pages.mut_iter() {
+		if page.content.contains("{{<") {
+			page.content = expandShortcodes(page.content.clone(), shortcodes, beforeMarkdown);
+		}
+		for block in page.blocks.mut_iter() {
+			for part in block.content.mut_iter() {
+				let (_, ref mut partContent) = *part;
+				if partContent.contains("{{<") {
+					*partContent = expandShortcodes(partContent.clone(), shortcodes, beforeMarkdown);
+				}
+			}
+		}
+	}
+}
+
+//Arguments of a shortcode call: name="value", name='value' or name=value. Arguments without a name are numbered from 0.
+fn parseShortcodeArgs(text: &str) -> Vec<(~str,~str)> {
+	let mut args: Vec<(~str,~str)> = Vec::new();
+	let mut rest = text.trim();
+	let mut position = 0;
+	while rest != "" {
+		let nameEnd = rest.find(|c: char| c == '=' || c == ' ' || c == '"' || c == '\'').unwrap_or(rest.len());
+		let mut name = "".to_owned();
+		if nameEnd < rest.len() && rest.char_at(nameEnd) == '=' {
+			name = rest.slice_to(nameEnd).to_owned();
+			rest = rest.slice_from(nameEnd + 1);
+		}
+		let value;
+		if rest.starts_with("\"") || rest.starts_with("'") {
+			let quote = rest.char_at(0);
+			let valueEnd = rest.slice_from(1).find(quote).map_or(rest.len(), |i| i + 1);
+			value = rest.slice(1, valueEnd).to_owned();
+			rest = if valueEnd < rest.len() { rest.slice_from(valueEnd + 1) } else { "" };
+		}
+		else {
+			let valueEnd = rest.find(' ').unwrap_or(rest.len());
+			value = rest.slice_to(valueEnd).to_owned();
+			rest = rest.slice_from(valueEnd);
+		}
+		if name == "".to_owned() {
+			name = position.to_str();
+			position += 1;
+		}
+		args.push((name, value));
+		rest = rest.trim_left();
+	}
+	return args;
+}
+
+//Find {{< /name >}} in text. Returns where it starts and its length.
+fn findShortcodeEnd(text: &str, name: &str) -> Option<(uint, uint)> {
+	let mut from = 0;
+	loop {
+		let start = match text.slice_from(from).find_str("{{<") {
+			Some(i) => from + i,
+			None => { return None }
+		};
+		let end = match text.slice_from(start).find_str(">}}") {
+			Some(i) => start + i,
+			None => { return None }
+		};
+		if text.slice(start + 3, end).trim() == "/" + name {
+			return Some((start, end + 3 - start));
+		}
+		from = end + 3;
+	}
+}
+
+//Replace calls of the shortcodes that are expanded at this point: {{< name args >}}, or {{< name args >}}...{{< /name >}}
+//for shortcodes with inner content. Calls of other shortcodes are left for the other pass.
+fn expandShortcodes(text: ~str, shortcodes: &Vec<Shortcode>, beforeMarkdown: bool) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text;
+	loop {
+		let start = match rest.find_str("{{<") {
+			Some(i) => i,
+			None => { break }
+		};
+		let end = match rest.slice_from(start).find_str(">}}") {
+			Some(i) => start + i,
+			None => { break }
+		};
+		let call = rest.slice(start + 3, end).trim().to_owned();
+		let name = call.split(' ').next().unwrap().to_owned();
+		let mut shortcode: Option<&Shortcode> = None;
+		let mut known = false;
+		for sc in shortcodes.iter() {
+			if sc.name == name {
+				known = true;
+				if sc.beforeMarkdown == beforeMarkdown {
+					shortcode = Some(sc);
+				}
+			}
+		}
+		let after = rest.slice_from(end + 3).to_owned();
+		match shortcode {
+			Some(sc) => {
+				let (inner, consumed) = match findShortcodeEnd(after, name) {
+					Some((i, length)) => (expandShortcodes(after.slice_to(i).to_owned(), shortcodes, beforeMarkdown), i + length),
+					None => ("".to_owned(), 0)
+				};
+				let mut expanded = sc.content.replace("{%inner}", inner.trim());
+				for arg in parseShortcodeArgs(call.slice_from(name.len())).iter() {
+					let (ref a, ref b) = *arg;
+					expanded = expanded.replace("{%arg." + *a + "}", *b);
+				}
+				//Arguments that weren't given are empty.
+				while expanded.contains("{%arg.") {
+					let argStart = expanded.find_str("{%arg.").unwrap();
+					let argEnd = match expanded.slice_from(argStart).find('}') {
+						Some(i) => argStart + i + 1,
+						None => { break }
+					};
+					expanded = expanded.slice_to(argStart) + expanded.slice_from(argEnd);
+				}
+				result = result + rest.slice_to(start) + expanded.trim();
+				rest = after.slice_from(consumed).to_owned();
+			},
+			None => {
+				if !known && !beforeMarkdown && !name.starts_with("/") {
+					warn(format!("Unknown shortcode {}.", name));
+				}
+				result = result + rest.slice_to(end + 3);
+				rest = after;
+			}
+		}
+	}
+	return result + rest;
+}
+
+//Put each template's content into {content} of the template it inherits from, and that into its parent's, and so on.
+//Head data and blocks are collected along the way, the template's own first. Region changes are collected the other
+//way round so that a template's changes are made after those of the templates it inherits from.
+fn processInheritance(templatesPre: &mut Vec<Template>) -> Vec<Template> {
+	let mut templates: Vec<Template> = Vec::new();
+	for template in templatesPre.iter() {
+		let mut resolved = template.clone();
+		let mut chain: Vec<~str> = vec!(template.name.clone());
+		let mut parentName = template.inherit.clone();
+		//The chain stops templates that inherit from each other from looping forever.
+		while parentName != "".to_owned() && !chain.contains(&parentName) {
+			match templatesPre.iter().position(|t| t.name == parentName) {
+				Some(i) => {
+					let parent = templatesPre.get(i);
+					resolved.headData.push_all(parent.headData.as_slice());
+					resolved.blockTemplates.push_all(parent.blockTemplates.as_slice());
+					resolved.content = parent.content.replace("{content}", resolved.content);
+					resolved.regions = parent.regions.clone().append(resolved.regions.as_slice());
+					chain.push(parentName.clone());
+					parentName = parent.inherit.clone();
+				},
+				None => {
+					warn(format!("Template {} inherits from {}, which doesn't exist.", chain.last().unwrap(), parentName));
+					break;
+				}
+			}
+		}
+		templates.push(resolved);
+	}
+	return templates;
+}
+
+struct Page {
+	path: ~str,
+	linkName: ~str,
+	title: ~str,
+	template: ~str,
+	blocks: Vec<Block>,
+	headData: Vec<~str>,
+	content: ~str,
+	sourcePath: ~str, //Path of the content file relative to /content
+	paginate: uint, //Number of listed pages per output page. 0 if this is not a listing page.
+	paginateSection: ~str, //Content subdirectory to list. Empty lists every page.
+	draft: bool,
+	publishDate: ~str,
+	expiryDate: ~str,
+	language: ~str,
+	vars: Vec<(~str,~str)>, //Every config value, matched with {%page.key}
+	toc: ~str, //Table of contents built from the page's headings, matched with {toc}
+	headings: Vec<(uint, ~str, ~str)>, //(level, id, text) of each heading in the page content
+	regions: Vec<Region> //Changes to regions of the page's template
 }
 
 impl Clone for Page {
@@ -404,257 +2771,2032 @@ impl Clone for Page {
 			template: self.template.clone(),
 			blocks: self.blocks.clone(),
 			headData: self.headData.clone(),
-			content: self.content.clone()
+			content: self.content.clone(),
+			sourcePath: self.sourcePath.clone(),
+			paginate: self.paginate,
+			paginateSection: self.paginateSection.clone(),
+			draft: self.draft,
+			publishDate: self.publishDate.clone(),
+			expiryDate: self.expiryDate.clone(),
+			language: self.language.clone(),
+			vars: self.vars.clone(),
+			toc: self.toc.clone(),
+			headings: self.headings.clone(),
+			regions: self.regions.clone()
+		};
+		return myPage;
+	}
+}
+
+struct Block {
+	name: ~str,
+	content: Vec<(~str,~str)> //Vector of tuples: (variable, content)
+}
+
+impl Clone for Block {
+	fn clone(&self) -> Block {
+		let myBlock = Block { name: self.name.clone(), content: self.content.clone() };
+		return myBlock;
+	}
+}
+
+fn loadContent(contentFiles: Vec<(Path, SourceFile)>, pathToContent: &Path, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, includes: &Vec<(~str,~str)>, hostPath: &str) -> Vec<Page> {
+	let mut pages: Vec<Page> = Vec::new();
+	for &(ref file, ref source) in contentFiles.iter() {
+		let mut myPage = Page {path: "".to_owned(), linkName: "".to_owned(), title: "".to_owned(), template: "".to_owned(), blocks: Vec::new(), headData: Vec::new(), content: "".to_owned(), sourcePath: "".to_owned(), paginate: 0, paginateSection: "".to_owned(), draft: false, publishDate: "".to_owned(), expiryDate: "".to_owned(), language: "".to_owned(), vars: Vec::new(), toc: "".to_owned(), headings: Vec::new(), regions: loadRegions(source, includes)};
+		match file.path_relative_from(pathToContent) {
+			Some(rel) => { myPage.sourcePath = rel.as_str().unwrap().to_owned() },
+			None => { myPage.sourcePath = file.as_str().unwrap().to_owned() }
+		}
+		//vars with this page's config values on top, used for the rest of the file.
+		let mut lineVars = vars.clone();
+		for section in source.sections.iter() {
+			match section.name.as_slice() {
+				"config" => {
+					for item in section.items.iter() {
+						let curLine = expandLine(item.text.clone(), &lineVars, internalLinks, includes, false);
+						let key = curLine.split_str(":").next().unwrap().trim().to_owned();
+						match curLine.split_str(":").next().unwrap() {
+							"path" => {
+								myPage.path = configValue(curLine.as_slice());
+							},
+							"linkName" => {
+								myPage.linkName = configValue(curLine.as_slice());
+							},
+							"title" => {
+								myPage.title = configValue(curLine.as_slice());
+							},
+							"template" => {
+								myPage.template = configValue(curLine.as_slice());
+							},
+							"paginate" => {
+								match from_str::<uint>(configValue(curLine.as_slice())) {
+									Some(n) => { myPage.paginate = n },
+									None => { warn(format!("paginate for content file {} is not a number.", file.filename_str().unwrap())) }
+								}
+							},
+							"paginateSection" => {
+								myPage.paginateSection = configValue(curLine.as_slice());
+							},
+							"draft" => {
+								myPage.draft = configValue(curLine.as_slice()) == "true".to_owned();
+							},
+							"publishDate" => {
+								myPage.publishDate = configValue(curLine.as_slice());
+							},
+							"expiryDate" => {
+								myPage.expiryDate = configValue(curLine.as_slice());
+							}
+							_ => { }
+						}
+						//Every key, known or not, is kept on the page. Page values override vars of the same name.
+						if key != "".to_owned() {
+							let value = configValue(curLine.as_slice());
+							setVar(&mut lineVars, "page." + key, value.clone());
+							setVar(&mut lineVars, key.clone(), value.clone());
+							setVar(&mut myPage.vars, key, value);
+						}
+					}
+				},
+				"css" => {
+					for item in section.items.iter() {
+						myPage.headData.push(cssTag(hostPath, expandLine(item.text.clone(), &lineVars, internalLinks, includes, true)));
+					}
+				},
+				"js" => {
+					for item in section.items.iter() {
+						myPage.headData.push(jsTag(hostPath, expandLine(item.text.clone(), &lineVars, internalLinks, includes, true)));
+					}
+				},
+				"blocks" => {
+					for blockNode in section.items.iter() {
+						let mut block = Block { name: blockNode.text.clone(), content: Vec::new() };
+						for part in blockNode.children.iter() {
+							if part.children.len() == 0 {
+								//A part with nothing under it is the block's default part, {} in the template.
+								block.content.push(("".to_owned(), expandLine(part.text.clone(), &lineVars, internalLinks, includes, true)));
+							}
+							else {
+								block.content.push((part.text.clone(), expandLine(nodeText(&part.children), &lineVars, internalLinks, includes, true)));
+							}
+						}
+						myPage.blocks.push(block);
+					}
+				},
+				_ => { }
+			}
+		}
+		myPage.content = insertIncludes(source.body.clone(), includes);
+		pages.push(myPage);
+	}
+	return pages;
+}
+
+//Settings can be set for the whole site in vars.txt and overridden in a page's config.
+fn pageSetting(page: &Page, vars: &Vec<(~str,~str)>, name: &str) -> Option<~str> {
+	match lookupVar(&page.vars, name) {
+		Some(value) => Some(value),
+		None => lookupVar(vars, name)
+	}
+}
+
+//Turn Markdown into HTML. Headings get ids for linking and are collected into the page's table of contents.
+//The tocMinLevel and tocMaxLevel settings (default 2 and 3) pick the headings that go in the table of contents,
+//headingAnchors: true adds a link to itself to every heading.
+fn mdToHTML(pages: &mut Vec<Page>, vars: &Vec<(~str,~str)>) {
+	for page in pages.mut_iter() {
+		let minLevel = pageSetting(page, vars, "tocMinLevel").and_then(|l| from_str::<uint>(l)).unwrap_or(2);
+		let maxLevel = pageSetting(page, vars, "tocMaxLevel").and_then(|l| from_str::<uint>(l)).unwrap_or(3);
+		let anchors = pageSetting(page, vars, "headingAnchors") == Some("true".to_owned());
+		let (content, headings) = addHeadingIds(renderMarkdown(page.content), anchors);
+		page.content = content;
+		page.toc = tableOfContents(&headings, minLevel, maxLevel);
+		page.headings = headings;
+		for block in page.blocks.mut_iter() {
+			for part in block.content.mut_iter() {
+				let (_, ref mut partContent) = *part;
+				//One line parts are usually a title or a link, so don't wrap them in a paragraph.
+				*partContent = if partContent.trim().contains_char('\n') { renderMarkdown(partContent.trim()) } else { renderInline(partContent.trim()) };
+			}
+		}
+	}
+}
+
+//Escape text for an attribute value. Also safe anywhere else in HTML.
+fn escapeHTML(text: &str) -> ~str {
+	return text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;").replace("'", "&#39;");
+}
+
+//Escape text for outside of tags.
+fn escapeText(text: &str) -> ~str {
+	return text.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;");
+}
+
+//Whether text at i starts an entity such as &amp; or &#39;.
+fn isEntity(text: &str, i: uint) -> bool {
+	let rest = text.slice_from(i + 1);
+	return match rest.find(';') {
+		Some(end) => end > 0 && rest.slice_to(end).chars().all(|c| c.is_alphanumeric() || c == '#'),
+		None => false
+	};
+}
+
+fn stripTags(html: &str) -> ~str {
+	let mut text = "".to_owned();
+	let mut inTag = false;
+	for c in html.chars() {
+		match c {
+			'<' => { inTag = true },
+			'>' => { inTag = false },
+			_ => { if !inTag { text.push_char(c) } }
+		}
+	}
+	return text;
+}
+
+fn flushParagraph(html: &mut ~str, paragraph: &mut Vec<~str>) {
+	if paragraph.len() > 0 {
+		let mut text = "".to_owned();
+		for line in paragraph.iter() {
+			if !text.is_empty() {
+				text.push_str("\n");
+			}
+			text.push_str(*line);
+		}
+		html.push_str("<p>" + renderInline(text) + "</p>\n");
+		*paragraph = Vec::new();
+	}
+}
+
+fn closeList(html: &mut ~str, listTag: &mut ~str) {
+	if !listTag.is_empty() {
+		html.push_str("</" + *listTag + ">\n");
+		*listTag = "".to_owned();
+	}
+}
+
+fn flushQuote(html: &mut ~str, quote: &mut Vec<~str>) {
+	if quote.len() > 0 {
+		let mut text = "".to_owned();
+		for line in quote.iter() {
+			text = text + *line + "\n";
+		}
+		html.push_str("<blockquote>\n" + renderMarkdown(text) + "</blockquote>\n");
+		*quote = Vec::new();
+	}
+}
+
+//Render Markdown blocks: # headings, paragraphs, > quotes, - and 1. lists, ``` code, and --- rules.
+//Lines starting with < or consisting of a {placeholder} are passed through untouched.
+fn renderMarkdown(text: &str) -> ~str {
+	let mut html = "".to_owned();
+	let mut paragraph: Vec<~str> = Vec::new();
+	let mut quote: Vec<~str> = Vec::new();
+	let mut listTag = "".to_owned();
+	let mut inCode = false;
+	let mut code = "".to_owned();
+	for line in text.lines() {
+		let trimmed = line.trim();
+		if inCode {
+			if trimmed.starts_with("```") {
+				html = html + "<pre><code>" + escapeHTML(code) + "</code></pre>\n";
+				code = "".to_owned();
+				inCode = false;
+			}
+			else {
+				code = code + line + "\n";
+			}
+			continue;
+		}
+		if trimmed.starts_with(">") {
+			flushParagraph(&mut html, &mut paragraph);
+			closeList(&mut html, &mut listTag);
+			quote.push(trimmed.slice_from(1).trim_left().to_owned());
+			continue;
+		}
+		flushQuote(&mut html, &mut quote);
+		let headingLevel = trimmed.chars().take_while(|c| *c == '#').count();
+		let orderedItem = match trimmed.find_str(". ") {
+			Some(i) => i > 0 && trimmed.slice_to(i).chars().all(|c| c.is_digit()),
+			None => false
+		};
+		if trimmed.starts_with("```") {
+			flushParagraph(&mut html, &mut paragraph);
+			closeList(&mut html, &mut listTag);
+			inCode = true;
+		}
+		else if trimmed == "" {
+			flushParagraph(&mut html, &mut paragraph);
+			closeList(&mut html, &mut listTag);
+		}
+		else if headingLevel >= 1 && headingLevel <= 6 && trimmed.slice_from(headingLevel).starts_with(" ") {
+			flushParagraph(&mut html, &mut paragraph);
+			closeList(&mut html, &mut listTag);
+			let level = headingLevel.to_str();
+			html = html + "<h" + level + ">" + renderInline(trimmed.slice_from(headingLevel).trim().trim_right_chars('#').trim()) + "</h" + level + ">\n";
+		}
+		else if trimmed == "---" || trimmed == "***" || trimmed == "___" {
+			flushParagraph(&mut html, &mut paragraph);
+			closeList(&mut html, &mut listTag);
+			html = html + "<hr>\n";
+		}
+		else if trimmed.starts_with("- ") || trimmed.starts_with("* ") || trimmed.starts_with("+ ") || orderedItem {
+			flushParagraph(&mut html, &mut paragraph);
+			let tag = if orderedItem { "ol" } else { "ul" };
+			if listTag.as_slice() != tag {
+				closeList(&mut html, &mut listTag);
+				html = html + "<" + tag + ">\n";
+				listTag = tag.to_owned();
+			}
+			let item = if orderedItem { trimmed.slice_from(trimmed.find_str(". ").unwrap() + 2) } else { trimmed.slice_from(2) };
+			html = html + "<li>" + renderInline(item.trim()) + "</li>\n";
+		}
+		else if paragraph.len() == 0 && (trimmed.starts_with("<") || (trimmed.starts_with("{") && trimmed.ends_with("}"))) {
+			closeList(&mut html, &mut listTag);
+			html = html + line + "\n";
+		}
+		else {
+			closeList(&mut html, &mut listTag);
+			paragraph.push(trimmed.to_owned());
+		}
+	}
+	if inCode {
+		html = html + "<pre><code>" + escapeHTML(code) + "</code></pre>\n";
+	}
+	flushParagraph(&mut html, &mut paragraph);
+	flushQuote(&mut html, &mut quote);
+	closeList(&mut html, &mut listTag);
+	return html;
+}
+
+//Split "[text](url)" at the start of text into text, url and the length of the whole link.
+fn parseLink(text: &str) -> Option<(~str, ~str, uint)> {
+	if !text.starts_with("[") {
+		return None;
+	}
+	let textEnd = match text.find_str("](") {
+		Some(i) => i,
+		None => { return None }
+	};
+	let urlEnd = match text.slice_from(textEnd).find(')') {
+		Some(i) => textEnd + i,
+		None => { return None }
+	};
+	return Some((text.slice(1, textEnd).to_owned(), text.slice(textEnd + 2, urlEnd).trim().to_owned(), urlEnd + 1));
+}
+
+//Render Markdown inside a line: **bold**, *italic*, `code`, [links](url) and ![images](url).
+fn renderInline(text: &str) -> ~str {
+	let mut html = "".to_owned();
+	let mut i = 0;
+	while i < text.len() {
+		let rest = text.slice_from(i);
+		if rest.starts_with("`") {
+			match rest.slice_from(1).find('`') {
+				Some(j) => {
+					html = html + "<code>" + escapeHTML(rest.slice(1, j + 1)) + "</code>";
+					i += j + 2;
+					continue;
+				},
+				None => { }
+			}
+		}
+		else if rest.starts_with("**") {
+			match rest.slice_from(2).find_str("**") {
+				Some(j) => {
+					html = html + "<strong>" + renderInline(rest.slice(2, j + 2)) + "</strong>";
+					i += j + 4;
+					continue;
+				},
+				None => { }
+			}
+		}
+		else if rest.starts_with("*") && !rest.starts_with("* ") {
+			match rest.slice_from(1).find('*') {
+				Some(j) => {
+					html = html + "<em>" + renderInline(rest.slice(1, j + 1)) + "</em>";
+					i += j + 2;
+					continue;
+				},
+				None => { }
+			}
+		}
+		else if rest.starts_with("![") {
+			match parseLink(rest.slice_from(1)) {
+				Some((alt, src, length)) => {
+					html = html + "<img src='" + src + "' alt='" + escapeHTML(alt) + "'>";
+					i += length + 1;
+					continue;
+				},
+				None => { }
+			}
+		}
+		else if rest.starts_with("[") {
+			match parseLink(rest) {
+				Some((linkText, href, length)) => {
+					html = html + "<a href='" + href + "'>" + renderInline(linkText) + "</a>";
+					i += length;
+					continue;
+				},
+				None => { }
+			}
+		}
+		let range = text.char_range_at(i);
+		//A & or < that doesn't start an entity, a tag or a shortcode is text.
+		if range.ch == '&' && !isEntity(text, i) {
+			html.push_str("&amp;");
+		}
+		else if range.ch == '<' && !html.ends_with("{{") && !rest.slice_from(1).starts_with("/") && !rest.slice_from(1).starts_with("!") && !rest.slice_from(1).chars().next().map_or(false, |c| c.is_alphabetic()) {
+			html.push_str("&lt;");
+		}
+		else {
+			html.push_char(range.ch);
+		}
+		i = range.next;
+	}
+	return html;
+}
+
+//Turn heading text into an id: lowercase letters and digits with dashes between words.
+fn slugify(text: &str) -> ~str {
+	let mut slug = "".to_owned();
+	let mut dash = false;
+	for c in text.chars() {
+		if c.is_alphanumeric() {
+			if dash && !slug.is_empty() {
+				slug.push_char('-');
+			}
+			dash = false;
+			slug.push_char(c.to_lowercase());
+		}
+		else if c == ' ' || c == '-' || c == '_' {
+			dash = true;
+		}
+	}
+	return slug;
+}
+
+//slug, or slug-1, slug-2... if it is already taken.
+fn uniqueSlug(slug: ~str, usedIds: &mut Vec<~str>) -> ~str {
+	let base = if slug.is_empty() { "section".to_owned() } else { slug };
+	let mut id = base.clone();
+	let mut n = 1;
+	while usedIds.contains(&id) {
+		id = base + "-" + n.to_str();
+		n += 1;
+	}
+	usedIds.push(id.clone());
+	return id;
+}
+
+//Value of a name='value' or name="value" attribute in the attributes part of a tag.
+fn attributeValue(attrs: &str, name: &str) -> Option<~str> {
+	for quote in ["'", "\""].iter() {
+		let marker = " " + name + "=" + *quote;
+		match attrs.find_str(marker) {
+			Some(i) => {
+				let from = i + marker.len();
+				match attrs.slice_from(from).find_str(*quote) {
+					Some(j) => { return Some(attrs.slice(from, from + j).to_owned()) },
+					None => { }
+				}
+			},
+			None => { }
+		}
+	}
+	return None;
+}
+
+//Index of the next <h1>-<h6> opening tag.
+fn findHeading(text: &str) -> Option<uint> {
+	let bytes = text.as_bytes();
+	let mut from = 0;
+	loop {
+		match text.slice_from(from).find_str("<h") {
+			Some(i) => {
+				let at = from + i;
+				if at + 3 < text.len() && bytes[at + 2] >= '1' as u8 && bytes[at + 2] <= '6' as u8 && (bytes[at + 3] == '>' as u8 || bytes[at + 3] == ' ' as u8) {
+					return Some(at);
+				}
+				from = at + 2;
+			},
+			None => { return None }
+		}
+	}
+}
+
+//Give every heading an id made from its text, unless it already has one. Ids used twice on a page get -1, -2...
+//Returns the new HTML and the (level, id, text) of each heading.
+fn addHeadingIds(html: ~str, anchors: bool) -> (~str, Vec<(uint, ~str, ~str)>) {
+	let mut result = "".to_owned();
+	let mut headings: Vec<(uint, ~str, ~str)> = Vec::new();
+	let mut usedIds: Vec<~str> = Vec::new();
+	let mut rest = html;
+	loop {
+		let start = match findHeading(rest) {
+			Some(i) => i,
+			None => { break }
+		};
+		let level = rest.slice(start + 2, start + 3);
+		let tagEnd = start + rest.slice_from(start).find('>').unwrap();
+		let close = "</h" + level + ">";
+		let closeStart = match rest.slice_from(tagEnd).find_str(close) {
+			Some(i) => tagEnd + i,
+			None => { break }
+		};
+		let mut attrs = rest.slice(start + 3, tagEnd).to_owned();
+		let inner = rest.slice(tagEnd + 1, closeStart);
+		let text = stripTags(inner).trim().to_owned();
+		let id = match attributeValue(attrs, "id") {
+			Some(id) => {
+				usedIds.push(id.clone());
+				id
+			},
+			None => {
+				let id = uniqueSlug(slugify(text), &mut usedIds);
+				attrs = attrs + " id='" + id + "'";
+				id
+			}
+		};
+		let anchor = if anchors { "<a class='anchor' href='#" + id + "' aria-hidden='true'>#</a>" } else { "".to_owned() };
+		result = result + rest.slice_to(start) + "<h" + level + attrs + ">" + inner + anchor + close;
+		headings.push((from_str::<uint>(level).unwrap(), id, text));
+		let next = rest.slice_from(closeStart + close.len()).to_owned();
+		rest = next;
+	}
+	return (result + rest, headings);
+}
+
+//Nested list of links to the headings from minLevel to maxLevel.
+fn tableOfContents(headings: &Vec<(uint, ~str, ~str)>, minLevel: uint, maxLevel: uint) -> ~str {
+	let mut toc = "".to_owned();
+	//Levels of the lists that are open.
+	let mut open: Vec<uint> = Vec::new();
+	for heading in headings.iter() {
+		let (level, ref id, ref text) = *heading;
+		if level < minLevel || level > maxLevel {
+			continue;
+		}
+		if open.len() == 0 {
+			toc = toc + "<ul class='toc'>";
+			open.push(level);
+		}
+		else if level > *open.last().unwrap() {
+			toc = toc + "\n<ul>";
+			open.push(level);
+		}
+		else {
+			while open.len() > 1 && level < *open.last().unwrap() {
+				toc = toc + "</li>\n</ul>";
+				open.pop();
+			}
+			toc = toc + "</li>";
+		}
+		toc = toc + "\n<li><a href='#" + *id + "'>" + *text + "</a>";
+	}
+	for _ in open.iter() {
+		toc = toc + "</li>\n</ul>";
+	}
+	return toc;
+}
+
+/*
+struct Page {
+	path: ~str,
+	linkName: ~str,
+	title: ~str,
+	template: ~str,
+	blocks: Vec<Block>,
+	headData: Vec<~str>,
+	content: ~str
+}
+
+struct Block {
+	name: ~str,
+	content: Vec<(~str,~str)> //Vector of tuples: (variable, content)
+}
+
+struct Template {
+	name: ~str,
+	inherit: ~str,
+	headData: Vec<~str>,
+	blockTemplates: Vec<(~str,~str)>,
+	content: ~str
+}*/
+
+fn processContent(pages: Vec<Page>, templates: Vec<Template>, resourceNames: Vec<(~str,~str)>, globalCSSJS: Vec<~str>, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, dataLists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>, contentInfo: &Vec<ContentInfo>, hostPath: &str, pageReports: &mut Vec<PageReport>) -> Vec<(~str,~str)> {
+	let mut returnVec: Vec<(~str,~str)> = Vec::new();
+	for page in pages.iter() {
+		let pageURL = page.path.to_owned();
+		let mut pageContent = "".to_owned();
+		let mut foundTemplate = false;
+		for template in templates.iter() {
+			if template.name.trim() == page.template.trim() {
+				foundTemplate = true;
+				let headDataVec = page.headData.clone().append(template.headData.as_slice());
+				let mut headDataStr = "".to_owned();
+				for line in headDataVec.iter() {
+					headDataStr = headDataStr + "\n" + line.to_owned();
+				}
+				//(block name, every instance of it on the page). A child template's block wins over its parent's.
+				let mut blockValues: Vec<(~str,~str)> = Vec::new();
+				let mut blockNames: Vec<~str> = Vec::new();
+				for blockTemplate in template.blockTemplates.iter() {
+					let (ref blockTempName, ref blockTempCont) = *blockTemplate;
+					if blockNames.contains(blockTempName) {
+						continue;
+					}
+					blockNames.push(blockTempName.clone());
+					let mut myBlocksStr = "".to_owned();
+					for block in page.blocks.iter() {
+						if block.name == *blockTempName {
+							//Parts the page leaves out are filled in from {part=default}.
+							myBlocksStr.push_str(fillPlaceholders(blockTempCont.as_slice(), &block.content, None));
+						}
+					}
+					if page.blocks.iter().any(|block| block.name == *blockTempName) {
+						blockValues.push((blockTempName.clone(), myBlocksStr));
+					}
+				}
+				//Blocks the page has no instance of are replaced with the default from {block=default}, or nothing.
+				//This is done before the page content goes in so that text in it is never taken for a block.
+				pageContent = fillPlaceholders(template.content.as_slice(), &blockValues, Some(&blockNames)).replace("{content}", page.content);
+				if pageContent.contains("{#region ") {
+					//The page's changes to regions come after its template's.
+					for region in template.regions.iter().chain(page.regions.iter()) {
+						pageContent = applyRegion(pageContent, region);
+					}
+					pageContent = removeRegionTags(pageContent);
+				}
+				pageContent = pageContent.replace("<head>", "<head>" + headDataStr);
+			}
+		}
+		if !foundTemplate {
+			//The page would be empty. Fail the build rather than publish it.
+			reportError(format!("{}: There is no template {}.", page.sourcePath, page.template.trim()));
+			continue;
+		}
+		let mut globalCSSJSStr = "".to_owned();
+		for cssJsLine in globalCSSJS.iter() {
+			globalCSSJSStr = globalCSSJSStr + "\n" + *cssJsLine;
+		}
+		let metaTagsStr = metaTags(page, vars, pageContent);
+		pageContent = pageContent.replace("<head>", "<head>\n<title>" + escapeText(page.title.trim()) + "</title>" + metaTagsStr + "\n" + globalCSSJSStr);
+		//Conditions on vars and lists first so that vars in parts that are left out don't matter.
+		if pageContent.contains("{#if ") {
+			pageContent = expandConditionals(pageContent, |name| conditionValue(name, page, vars, dataLists), false);
+		}
+		//Page config values first so they win over vars of the same name, then anything the templates still contain.
+		pageContent = replacePageVars(pageContent, &page.vars);
+		pageContent = replaceVars(pageContent, vars, internalLinks, true);
+		for list in dataLists.iter() {
+			let (ref name, ref items) = *list;
+			pageContent = expandLoops(pageContent, name.as_slice(), items, dataLists);
+		}
+		pageContent = pageContent.replace("{toc}", page.toc);
+		if pageContent.contains("{nav") {
+			pageContent = insertMenus(pageContent, page, contentInfo);
+		}
+		if pageContent.contains("{breadcrumbs") {
+			pageContent = insertBreadcrumbs(pageContent, page, contentInfo, vars);
+		}
+		//replace resource names
+		for resource in resourceNames.iter() {
+			//(~str,~str) (filename, path)
+			let (ref a, ref b) = *resource;
+			pageContent = pageContent.replace("{$" + a.trim() + "}", *b);
+		}
+		let mut files = if page.paginate > 0 { paginate(page, pageContent, &pages, hostPath) } else { vec!((pageURL, pageContent)) };
+		//What is left is conditions on things that don't exist, such as a field a loop item doesn't have. This comes
+		//after paginate, which expands conditions on the paginator and on the items of its loops.
+		for file in files.mut_iter() {
+			let (_, ref mut text) = *file;
+			if text.contains("{#if ") {
+				*text = expandConditionals(text.clone(), |_| None, true);
+			}
+		}
+		let chain = templateChain(page.template.trim(), &templates);
+		for file in files.iter() {
+			let (ref a, ref b) = *file;
+			pageReports.push(PageReport { source: page.sourcePath.clone(), templates: chain.clone(), output: a.trim().to_owned(), size: b.len() });
+		}
+		returnVec.push_all(files.as_slice());
+	}
+	return returnVec;
+}
+
+//Replace {name} and {name=default} placeholders with the value of name, or without one with the default.
+//With declared only those names are replaced, and with nothing if they have no default. Other placeholders, such as
+//{nav} or {%var}, are left for later.
+fn fillPlaceholders(text: &str, values: &Vec<(~str,~str)>, declared: Option<&Vec<~str>>) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text;
+	loop {
+		let start = match rest.find('{') {
+			Some(i) => i,
+			None => { break }
+		};
+		result.push_str(rest.slice_to(start));
+		rest = rest.slice_from(start);
+		let nameEnd = rest.slice_from(1).find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).map_or(rest.len(), |i| i + 1);
+		if nameEnd >= rest.len() || (rest.char_at(nameEnd) != '}' && rest.char_at(nameEnd) != '=') {
+			result.push_char('{');
+			rest = rest.slice_from(1);
+			continue;
+		}
+		let name = rest.slice(1, nameEnd);
+		let isDeclared = declared.map_or(true, |names| names.iter().any(|n| n.as_slice() == name));
+		//The default may contain placeholders of its own, e.g. {title={%page.title}}.
+		let end = match findClosingTag(rest.slice_from(1), "{", "}") {
+			Some(i) => i + 1,
+			None => {
+				result.push_char('{');
+				rest = rest.slice_from(1);
+				continue;
+			}
+		};
+		let default = if rest.char_at(nameEnd) == '=' { Some(rest.slice(nameEnd + 1, end)) } else { None };
+		match (lookupVar(values, name), default) {
+			(Some(value), _) => { result.push_str(value) },
+			(None, Some(default)) if isDeclared => { result.push_str(default) },
+			(None, None) if isDeclared && declared.is_some() => { },
+			_ => { result.push_str(rest.slice_to(end + 1)) }
+		}
+		rest = rest.slice_from(end + 1);
+	}
+	result.push_str(rest);
+	return result;
+}
+
+//Names of a template and the templates it inherits from, nearest first.
+fn templateChain(name: &str, templates: &Vec<Template>) -> Vec<~str> {
+	let mut chain: Vec<~str> = Vec::new();
+	let mut next = name.to_owned();
+	while next != "".to_owned() && !chain.contains(&next) {
+		chain.push(next.clone());
+		let mut parent = "".to_owned();
+		for template in templates.iter() {
+			if template.name.trim() == next.as_slice() {
+				parent = template.inherit.trim().to_owned();
+			}
+		}
+		next = parent;
+	}
+	return chain;
+}
+
+//Replace {nav.name} with the generated menu of that name, and {nav} with the main menu.
+//Pages are listed in a menu with "menu: name" in their config.
+fn insertMenus(mut text: ~str, page: &Page, contentInfo: &Vec<ContentInfo>) -> ~str {
+	let mut menuNames: Vec<~str> = Vec::new();
+	for info in contentInfo.iter() {
+		for menu in info.menus.iter() {
+			if !menuNames.contains(menu) {
+				menuNames.push(menu.clone());
+			}
+		}
+	}
+	for menu in menuNames.iter() {
+		let mut entries: Vec<&ContentInfo> = Vec::new();
+		for info in contentInfo.iter() {
+			if info.published && info.language == page.language && info.menus.contains(menu) {
+				entries.push(info);
+			}
+		}
+		let (menuStr, _) = menuHTML(&entries, "", page.path.trim(), "nav nav-" + *menu, 0);
+		text = text.replace("{nav." + *menu + "}", menuStr);
+		if menu.as_slice() == "main" {
+			text = text.replace("{nav}", menuStr);
+		}
+	}
+	return text;
+}
+
+//Warn about menu entries that would be left out of their menu because their parent isn't in it.
+fn checkMenus(contentInfo: &Vec<ContentInfo>) {
+	for info in contentInfo.iter() {
+		if !info.published || info.parent.is_empty() {
+			continue;
+		}
+		for menu in info.menus.iter() {
+			let parentInMenu = contentInfo.iter().any(|other| other.published && other.language == info.language && other.linkName == info.parent && other.menus.contains(menu));
+			if !parentInMenu {
+				warn(format!("{} is not in menu {} because its parent {} isn't in it.", info.file.as_str().unwrap(), *menu, info.parent));
+			}
+		}
+	}
+}
+
+//Nested <ul> of the menu entries under parent, sorted by weight. The current page's entry gets class='active' and
+//the entries above it class='active-parent'. Also returns whether the current page is in this part of the menu.
+fn menuHTML(entries: &Vec<&ContentInfo>, parent: &str, currentPath: &str, listClass: &str, depth: uint) -> (~str, bool) {
+	let mut children: Vec<&ContentInfo> = Vec::new();
+	for entry in entries.iter() {
+		if entry.parent.as_slice() == parent {
+			children.push(*entry);
+		}
+	}
+	//The depth limit stops pages that are each other's parent from recursing forever.
+	if children.len() == 0 || depth > 16 {
+		return ("".to_owned(), false);
+	}
+	children.sort_by(|a, b| {
+		if a.weight != b.weight { a.weight.cmp(&b.weight) } else { a.linkName.cmp(&b.linkName) }
+	});
+	let mut menuStr = if listClass.is_empty() { "\n<ul>".to_owned() } else { "\n<ul class='" + listClass + "'>" };
+	let mut containsCurrent = false;
+	for child in children.iter() {
+		let (submenu, submenuCurrent) = menuHTML(entries, child.linkName, currentPath, "", depth + 1);
+		let isCurrent = child.path.as_slice() == currentPath;
+		let class = if isCurrent { " class='active'" } else if submenuCurrent { " class='active-parent'" } else { "" };
+		menuStr = menuStr + "\n<li" + class + "><a href='" + child.linkPath + "'>" + escapeText(child.linkName) + "</a>" + submenu + "</li>";
+		containsCurrent = containsCurrent || isCurrent || submenuCurrent;
+	}
+	return (menuStr + "\n</ul>", containsCurrent);
+}
+
+//Directory part of a content path, "" at the top level.
+fn contentDir(sourcePath: &str) -> ~str {
+	match sourcePath.rfind('/') {
+		Some(i) => sourcePath.slice_to(i).to_owned(),
+		None => "".to_owned()
+	}
+}
+
+fn isIndexPage(sourcePath: &str) -> bool {
+	let fileName = sourcePath.slice_from(sourcePath.rfind('/').map_or(0, |i| i + 1));
+	return fileName.split('.').next().unwrap() == "index";
+}
+
+//The pages above a page, nearest first. A page's parent is the page named by its parent config key, or otherwise
+//the index page of its content directory (for an index page, of the directory above).
+fn pageAncestors<'a>(translationKey: &str, parent: &str, language: &str, contentInfo: &'a Vec<ContentInfo>) -> Vec<&'a ContentInfo> {
+	let mut ancestors: Vec<&'a ContentInfo> = Vec::new();
+	let mut key = translationKey.to_owned();
+	let mut parentName = parent.to_owned();
+	//The limit stops pages that are each other's parent from looping forever.
+	for _ in range(0, 32) {
+		let mut next: Option<&'a ContentInfo> = None;
+		if parentName != "".to_owned() {
+			for info in contentInfo.iter() {
+				if info.published && info.language.as_slice() == language && info.linkName == parentName {
+					next = Some(info);
+				}
+			}
+		}
+		else if !(isIndexPage(key) && contentDir(key) == "".to_owned()) {
+			let dir = if isIndexPage(key) { contentDir(contentDir(key)) } else { contentDir(key) };
+			for info in contentInfo.iter() {
+				if info.published && info.language.as_slice() == language && isIndexPage(info.translationKey) && contentDir(info.translationKey) == dir {
+					next = Some(info);
+				}
+			}
+		}
+		match next {
+			Some(info) => {
+				ancestors.push(info);
+				key = info.translationKey.clone();
+				parentName = info.parent.clone();
+			},
+			None => { break }
+		}
+	}
+	return ancestors;
+}
+
+//Replace {breadcrumbs} with a trail of links to the pages above this one and {breadcrumbs.jsonld} with the same trail
+//as a BreadcrumbList. The JSON-LD uses the baseURL var for absolute URLs.
+fn insertBreadcrumbs(mut text: ~str, page: &Page, contentInfo: &Vec<ContentInfo>, vars: &Vec<(~str,~str)>) -> ~str {
+	let (_, translationKey) = contentLanguage(page.sourcePath, &vec!(page.language.clone()));
+	let parent = match lookupVar(&page.vars, "parent") { Some(p) => p, None => "".to_owned() };
+	let mut ancestors = pageAncestors(translationKey, parent, page.language, contentInfo);
+	ancestors.reverse();
+	let baseURL = match lookupVar(vars, "baseURL") { Some(url) => url.trim_right_chars('/').to_owned(), None => "".to_owned() };
+	let mut trail = "<nav class='breadcrumbs'>\n<ol>".to_owned();
+	let mut listItems = "".to_owned();
+	let mut position = 1;
+	for ancestor in ancestors.iter() {
+		trail = trail + "\n<li><a href='" + ancestor.linkPath + "'>" + escapeText(ancestor.linkName) + "</a></li>";
+		listItems = listItems + "\n{\"@type\": \"ListItem\", \"position\": " + position.to_str() + ", \"name\": " + json::String(ancestor.linkName.clone()).to_str() + ", \"item\": " + json::String(baseURL + "/" + ancestor.path).to_str() + "},";
+		position += 1;
+	}
+	trail = trail + "\n<li class='current'>" + escapeText(page.linkName.trim()) + "</li>\n</ol>\n</nav>";
+	listItems = listItems + "\n{\"@type\": \"ListItem\", \"position\": " + position.to_str() + ", \"name\": " + json::String(page.linkName.trim().to_owned()).to_str() + ", \"item\": " + json::String(baseURL + "/" + page.path.trim()).to_str() + "}";
+	let jsonLD = "<script type='application/ld+json'>\n{\"@context\": \"https://schema.org\", \"@type\": \"BreadcrumbList\", \"itemListElement\": [" + listItems + "\n]}\n</script>";
+	text = text.replace("{breadcrumbs.jsonld}", jsonLD);
+	text = text.replace("{breadcrumbs}", trail);
+	return text;
+}
+
+//Description, canonical link, Open Graph, Twitter card and JSON-LD tags for the head of a page. Values come from the
+//page's config (title, description, image, date, author, canonical, twitterCard) and otherwise from vars of the same
+//name, with siteName, twitterSite and baseURL for the site. "seo: false" in a page's config or vars.txt leaves them
+//out, and a tag the template already has, e.g. <meta property='og:image' ...>, isn't added again.
+fn metaTags(page: &Page, vars: &Vec<(~str,~str)>, pageContent: &str) -> ~str {
+	let value = |name: &str| -> ~str {
+		match lookupVar(&page.vars, name).or_else(|| lookupVar(vars, name)) {
+			Some(value) => value.trim().to_owned(),
+			None => "".to_owned()
+		}
+	};
+	if !isTrue(value("seo")) && !value("seo").is_empty() {
+		return "".to_owned();
+	}
+	let baseURL = value("baseURL").trim_right_chars('/').to_owned();
+	let absolute = |url: &str| -> ~str {
+		if url.is_empty() || url.contains("://") { url.to_owned() } else { baseURL + "/" + url.trim_left_chars('/') }
+	};
+	let title = page.title.trim().to_owned();
+	let description = value("description");
+	//An image in the page's config without a leading / is a file of its bundle, copied next to the page's output.
+	let image = match lookupVar(&page.vars, "image").map(|image| image.trim().to_owned()) {
+		Some(ref image) if !image.is_empty() && !image.starts_with("/") && !image.contains("://") => {
+			let dir = Path::new(page.path.trim()).dir_path();
+			absolute(if dir.as_str() == Some(".") { image.clone() } else { format!("{}/{}", dir.as_str().unwrap(), *image) })
+		},
+		_ => absolute(value("image"))
+	};
+	let date = value("date");
+	let author = value("author");
+	let siteName = value("siteName");
+	let canonical = if value("canonical").is_empty() { absolute(page.path.trim()) } else { absolute(value("canonical")) };
+	let twitterCard = if !value("twitterCard").is_empty() { value("twitterCard") } else if image.is_empty() { "summary".to_owned() } else { "summary_large_image".to_owned() };
+	let twitterSite = value("twitterSite");
+	let article = !date.is_empty();
+
+	//(attribute, name, value). Tags without a value are left out.
+	let tags: Vec<(&str, &str, ~str)> = vec!(
+		("name", "description", description.clone()),
+		("property", "og:title", title.clone()),
+		("property", "og:description", description.clone()),
+		("property", "og:type", (if article { "article" } else { "website" }).to_owned()),
+		("property", "og:url", canonical.clone()),
+		("property", "og:image", image.clone()),
+		("property", "og:site_name", siteName.clone()),
+		("property", "article:published_time", date.clone()),
+		("property", "article:author", author.clone()),
+		("name", "twitter:card", twitterCard),
+		("name", "twitter:site", twitterSite),
+		("name", "twitter:title", title.clone()),
+		("name", "twitter:description", description.clone()),
+		("name", "twitter:image", image.clone()));
+	let mut result = "".to_owned();
+	if !canonical.is_empty() && !hasHeadTag(pageContent, "canonical") {
+		result.push_str("\n<link rel='canonical' href='" + escapeHTML(canonical) + "'>");
+	}
+	for tag in tags.iter() {
+		let (attribute, name, ref content) = *tag;
+		if !content.is_empty() && !hasHeadTag(pageContent, name) {
+			result.push_str(format!("\n<meta {}='{}' content='{}'>", attribute, name, escapeHTML(*content)));
+		}
+	}
+
+	if !hasHeadTag(pageContent, "application/ld+json") {
+		let mut fields: Vec<~str> = Vec::new();
+		fields.push("\"@context\": \"https://schema.org\"".to_owned());
+		if article {
+			fields.push("\"@type\": \"Article\"".to_owned());
+			fields.push("\"headline\": " + jsonString(title));
+			fields.push("\"datePublished\": " + jsonString(date));
+			if !author.is_empty() {
+				fields.push("\"author\": {\"@type\": \"Person\", \"name\": " + jsonString(author) + "}");
+			}
+			if !image.is_empty() {
+				fields.push("\"image\": " + jsonString(image));
+			}
+			fields.push("\"mainEntityOfPage\": " + jsonString(canonical));
+		}
+		else {
+			fields.push("\"@type\": \"WebSite\"".to_owned());
+			fields.push("\"name\": " + jsonString(if siteName.is_empty() { title } else { siteName }));
+			fields.push("\"url\": " + jsonString(if baseURL.is_empty() { canonical } else { baseURL + "/" }));
+		}
+		if !description.is_empty() {
+			fields.push("\"description\": " + jsonString(description));
+		}
+		//</ in a value would end the script early.
+		result.push_str("\n<script type='application/ld+json'>\n{" + fields.connect(", ").replace("</", "<\\/") + "}\n</script>");
+	}
+	return result;
+}
+
+//Whether the page already has a head tag for name, e.g. og:title written in its template.
+fn hasHeadTag(pageContent: &str, name: &str) -> bool {
+	let head = match pageContent.find_str("</head>") {
+		Some(i) => pageContent.slice_to(i),
+		None => pageContent
+	};
+	return head.contains("'" + name + "'") || head.contains("\"" + name + "\"");
+}
+
+//Replace {%page.key} and, overriding vars.txt, {%key} with a page's config values.
+fn replacePageVars(text: ~str, pageVars: &Vec<(~str,~str)>) -> ~str {
+	return substituteVars(text, |name| {
+		if name.starts_with("page.") { lookupVar(pageVars, name.slice_from("page.".len())) } else { lookupVar(pageVars, name) }
+	}, true);
+}
+
+//Fields of a page that loops over pages can use, e.g. {%post.title} inside {#for post in paginator.pages}.
+fn pageItem(page: &Page, hostPath: &str) -> Vec<(~str,~str)> {
+	let mut item: Vec<(~str,~str)> = Vec::new();
+	item.push(("title".to_owned(), page.title.trim().to_owned()));
+	item.push(("linkName".to_owned(), page.linkName.trim().to_owned()));
+	item.push(("path".to_owned(), format!("{}/{}", hostPath, page.path.trim())));
+	for var in page.vars.iter() {
+		let (ref a, ref b) = *var;
+		if lookupVar(&item, a.as_slice()).is_none() {
+			item.push((a.to_owned(), b.to_owned()));
+		}
+	}
+	return item;
+}
+
+//Output path of page n of a listing. Page 1 keeps the listing's own path.
+//index.html listings continue in page/2/index.html, other listings in <name>/page/2/index.html.
+fn paginatedPath(path: &str, n: uint) -> ~str {
+	let path = path.trim();
+	if n == 1 {
+		return path.to_owned();
+	}
+	let prefix = if path.ends_with("index.html") {
+		path.slice_to(path.len() - "index.html".len()).to_owned()
+	}
+	else {
+		match path.rfind('.') {
+			Some(i) => path.slice_to(i).to_owned() + "/",
+			None => path.to_owned() + "/"
+		}
+	};
+	return prefix + "page/" + n.to_str() + "/index.html";
+}
+
+//URL of page n of a listing. hostPath is where the site is on its host, e.g. /blog, or empty.
+fn paginatedURL(path: &str, n: uint, hostPath: &str) -> ~str {
+	let outPath = paginatedPath(path, n);
+	if outPath.ends_with("/index.html") {
+		return format!("{}/{}", hostPath, outPath.slice_to(outPath.len() - "index.html".len()));
+	}
+	return format!("{}/{}", hostPath, outPath);
+}
+
+//Whether a content file is in section, a content subdirectory such as blog or docs/guides. Whole directory names are
+//compared, so blogroll/ is not in blog, and a language directory, e.g. de/ in de/blog/post.md, doesn't count.
+fn inSection(sourcePath: &str, language: &str, section: &str) -> bool {
+	let path = if !language.is_empty() && sourcePath.starts_with(language + "/") { sourcePath.slice_from(language.len() + 1) } else { sourcePath };
+	let dirs: Vec<&str> = path.split('/').collect();
+	let sectionDirs: Vec<&str> = section.trim().split('/').filter(|dir| !dir.is_empty()).collect();
+	//The last part of the path is the file name.
+	return dirs.len() > sectionDirs.len() && dirs.slice_to(sectionDirs.len()) == sectionDirs.as_slice();
+}
+
+//Split a listing page into output pages of page.paginate entries each.
+//The template iterates the entries with {#for item in paginator.pages}...{/for} and can use
+//{%paginator.pageNumber}, {%paginator.totalPages}, {%paginator.prev}, {%paginator.next} and {%paginator.pageLinks},
+//and test them with {#if paginator.next}.
+fn paginate(page: &Page, pageContent: ~str, pages: &Vec<Page>, hostPath: &str) -> Vec<(~str,~str)> {
+	let mut returnVec: Vec<(~str,~str)> = Vec::new();
+	let mut listed: Vec<&Page> = Vec::new();
+	for other in pages.iter() {
+		if other.paginate == 0 && inSection(other.sourcePath, other.language, page.paginateSection) {
+			listed.push(other);
+		}
+	}
+	//Newest first for pages with a publishDate, then by content file.
+	listed.sort_by(|a, b| {
+		if a.publishDate != b.publishDate { b.publishDate.cmp(&a.publishDate) } else { a.sourcePath.cmp(&b.sourcePath) }
+	});
+	let totalPages = if listed.len() == 0 { 1 } else { (listed.len() + page.paginate - 1) / page.paginate };
+	for pageNumber in range(1, totalPages + 1) {
+		let start = std::cmp::min((pageNumber - 1) * page.paginate, listed.len());
+		let end = std::cmp::min(start + page.paginate, listed.len());
+		let mut items: Vec<Vec<(~str,~str)>> = Vec::new();
+		for other in listed.slice(start, end).iter() {
+			items.push(pageItem(*other, hostPath));
+		}
+		let mut pageLinks = "".to_owned();
+		for n in range(1, totalPages + 1) {
+			if n == pageNumber {
+				pageLinks = pageLinks + "<span class='current'>" + n.to_str() + "</span>";
+			}
+			else {
+				pageLinks = pageLinks + "<a href='" + paginatedURL(page.path, n, hostPath) + "'>" + n.to_str() + "</a>";
+			}
+		}
+		let prev = if pageNumber > 1 { paginatedURL(page.path, pageNumber - 1, hostPath) } else { "".to_owned() };
+		let next = if pageNumber < totalPages { paginatedURL(page.path, pageNumber + 1, hostPath) } else { "".to_owned() };
+		let mut text = expandLoops(pageContent.clone(), "paginator.pages", &items, &Vec::new());
+		if text.contains("{#if ") {
+			text = expandConditionals(text, |name| {
+				match name {
+					"paginator.pages" => Some(items.len() > 0),
+					"paginator.prev" => Some(!prev.is_empty()),
+					"paginator.next" => Some(!next.is_empty()),
+					"paginator.pageLinks" => Some(totalPages > 1),
+					"paginator.pageNumber" | "paginator.totalPages" => Some(true),
+					_ => None
+				}
+			}, false);
+		}
+		text = text.replace("{%paginator.pageNumber}", pageNumber.to_str());
+		text = text.replace("{%paginator.totalPages}", totalPages.to_str());
+		text = text.replace("{%paginator.prev}", prev);
+		text = text.replace("{%paginator.next}", next);
+		text = text.replace("{%paginator.pageLinks}", pageLinks);
+		returnVec.push((paginatedPath(page.path, pageNumber), text));
+	}
+	return returnVec;
+}
+
+//Find the tag that closes a section opened just before text, skipping over nested sections. Returns its index in text.
+fn findClosingTag(text: &str, open: &str, close: &str) -> Option<uint> {
+	let mut depth = 0;
+	for (i, _) in text.char_indices() {
+		let rest = text.slice_from(i);
+		if rest.starts_with(open) {
+			depth += 1;
+		}
+		else if rest.starts_with(close) {
+			if depth == 0 {
+				return Some(i);
+			}
+			depth -= 1;
+		}
+	}
+	return None;
+}
+
+//Find the {#else} of a condition in its body, skipping over conditions nested in it. Returns its index in body.
+fn findElse(body: &str) -> Option<uint> {
+	let mut depth = 0;
+	for (i, _) in body.char_indices() {
+		let rest = body.slice_from(i);
+		if rest.starts_with("{#if ") {
+			depth += 1;
+		}
+		else if rest.starts_with("{/if}") {
+			depth -= 1;
+		}
+		else if rest.starts_with("{#else}") && depth == 0 {
+			return Some(i);
+		}
+	}
+	return None;
+}
+
+//Expand {#for item in listName}...{/for} loops. Each item is a vector of (field, value) tuples matched inside the loop
+//with {%item.field}. A field with an empty name is the item itself, matched with {%item}.
+//Loops over other lists are left in place. Lists in an item, e.g. team.0.skills, can be looped over inside the loop
+//with {#for skill in member.skills}.
+fn expandLoops(text: ~str, listName: &str, items: &Vec<Vec<(~str,~str)>>, lists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>) -> ~str {
+	return expandListLoops(text, listName, listName, items, lists);
+}
+
+//expandLoops for loops written as {#for x in loopName} over the list called listName, e.g. member.skills inside a
+//loop over team for team.0.skills.
+fn expandListLoops(text: ~str, loopName: &str, listName: &str, items: &Vec<Vec<(~str,~str)>>, lists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text;
+	loop {
+		let next = {
+			let start = match rest.find_str("{#for ") {
+				Some(i) => i,
+				None => { break }
+			};
+			let headerEnd = match rest.slice_from(start).find('}') {
+				Some(i) => start + i,
+				None => { break }
+			};
+			let bodyEnd = match findClosingTag(rest.slice_from(headerEnd + 1), "{#for ", "{/for}") {
+				Some(i) => headerEnd + 1 + i,
+				None => {
+					warn(format!("Loop {} is never closed.", rest.slice(start, headerEnd + 1)));
+					break
+				}
+			};
+			let header: Vec<&str> = rest.slice(start + "{#for ".len(), headerEnd).split_str(" in ").collect();
+			if header.len() != 2 || header.get(1).trim() != loopName {
+				//Not ours. Keep it, including its body, for whoever expands that list.
+				result = result + rest.slice_to(bodyEnd + "{/for}".len());
+			}
+			else {
+				let itemName = header.get(0).trim();
+				let loopBody = rest.slice(headerEnd + 1, bodyEnd);
+				result = result + rest.slice_to(start);
+				for (i, item) in items.iter().enumerate() {
+					//(name, value) of the item's fields as they are written in the loop, e.g. post.title.
+					let mut fields: Vec<(~str,~str)> = Vec::new();
+					for field in item.iter() {
+						let (ref a, ref b) = *field;
+						fields.push((if a.is_empty() { itemName.to_owned() } else { format!("{}.{}", itemName, *a) }, b.clone()));
+					}
+					let mut itemText = loopBody.to_owned();
+					if itemText.contains("{#for ") {
+						let prefix = format!("{}.{}.", listName, i);
+						for list in lists.iter() {
+							let (ref name, ref nestedItems) = *list;
+							if name.starts_with(prefix) && !name.slice_from(prefix.len()).contains_char('.') {
+								let nestedLoopName = format!("{}.{}", itemName, name.slice_from(prefix.len()));
+								itemText = expandListLoops(itemText, nestedLoopName, *name, nestedItems, lists);
+							}
+						}
+					}
+					if itemText.contains("{#if ") {
+						itemText = expandConditionals(itemText, |name| lookupVar(&fields, name).map(|value| isTrue(value.as_slice())), false);
+					}
+					result = result + substituteVars(itemText, |name| lookupVar(&fields, name), true);
+				}
+			}
+			rest.slice_from(bodyEnd + "{/for}".len()).to_owned()
+		};
+		rest = next;
+	}
+	return result + rest;
+}
+
+//Whether a var value counts as true in {#if name}: anything but nothing, false or 0.
+fn isTrue(value: &str) -> bool {
+	let value = value.trim();
+	return !(value.is_empty() || value == "false" || value == "0");
+}
+
+//Whether {#if name} is true for a page: a page config value, a var or a list with items in it.
+//None if there is nothing called name.
+fn conditionValue(name: &str, page: &Page, vars: &Vec<(~str,~str)>, lists: &Vec<(~str, Vec<Vec<(~str,~str)>>)>) -> Option<bool> {
+	let pageName = if name.starts_with("page.") { name.slice_from("page.".len()) } else { name };
+	match lookupVar(&page.vars, pageName) {
+		Some(value) => { return Some(isTrue(value.as_slice())) },
+		None => { }
+	}
+	match lookupVar(vars, name) {
+		Some(value) => { return Some(isTrue(value.as_slice())) },
+		None => { }
+	}
+	for list in lists.iter() {
+		let (ref listName, ref items) = *list;
+		if listName.as_slice() == name {
+			return Some(items.len() > 0);
+		}
+	}
+	return None;
+}
+
+//Expand {#if name}...{#else}...{/if}, keeping the first part if condition says name is true and otherwise the part
+//after {#else}, if there is one. {#if not name} is the other way round. Conditions on names condition doesn't know
+//are left for later, or with last taken to be false.
+fn expandConditionals(text: ~str, condition: |&str| -> Option<bool>, last: bool) -> ~str {
+	let mut result = "".to_owned();
+	let mut rest = text;
+	loop {
+		let next = {
+			let start = match rest.find_str("{#if ") {
+				Some(i) => i,
+				None => { break }
+			};
+			let headerEnd = match rest.slice_from(start).find('}') {
+				Some(i) => start + i,
+				None => { break }
+			};
+			let bodyEnd = match findClosingTag(rest.slice_from(headerEnd + 1), "{#if ", "{/if}") {
+				Some(i) => headerEnd + 1 + i,
+				None => {
+					warn(format!("Condition {} is never closed.", rest.slice(start, headerEnd + 1)));
+					break
+				}
+			};
+			let expression = rest.slice(start + "{#if ".len(), headerEnd).trim();
+			let (negate, name) = if expression.starts_with("not ") { (true, expression.slice_from("not ".len()).trim()) } else { (false, expression) };
+			let body = rest.slice(headerEnd + 1, bodyEnd);
+			let after = rest.slice_from(bodyEnd + "{/if}".len());
+			let value = match condition(name) {
+				Some(value) => Some(value),
+				None => if last { Some(false) } else { None }
+			};
+			match value {
+				Some(value) => {
+					result.push_str(rest.slice_to(start));
+					//{#else} of this condition, not of one inside it.
+					let (thenPart, elsePart) = match findElse(body) {
+						Some(i) => (body.slice_to(i), body.slice_from(i + "{#else}".len())),
+						None => (body, "")
+					};
+					//The part that is kept may have conditions of its own, so it is looked at again.
+					let mut kept = (if value != negate { thenPart } else { elsePart }).to_owned();
+					kept.push_str(after);
+					kept
+				},
+				None => {
+					result.push_str(rest.slice_to(bodyEnd + "{/if}".len()));
+					after.to_owned()
+				}
+			}
+		};
+		rest = next;
+	}
+	return result + rest;
+}
+
+//Name of the file in /output that lists what rgen generated there.
+static MANIFEST: &'static str = ".rgen-manifest";
+
+//Write the generated files to path. Returns their paths relative to it.
+fn outputFiles(files: Vec<(~str,~str)>, path: Path) -> Vec<~str> {
+	let mut written: Vec<~str> = Vec::new();
+	println!("Output Files:");
+	for file in files.iter() {
+		let (ref a, ref b) = *file;
+		println!("\t{}", a.trim());
+		match fs::mkdir(&path, io::UserRWX) {
+			Ok(_) => { },
+			Err(_) => { }
+		}
+		let myPath = Path::new(path.as_str().unwrap() + "/" + a.trim());
+		//Listing pages and nested paths go into subdirectories of /output.
+		match fs::mkdir_recursive(&myPath.dir_path(), io::UserRWX) {
+			Ok(_) => { },
+			Err(_) => { }
+		}
+		let mut writer = BufferedWriter::new(File::create(&myPath));
+		let mut ok = true;
+		match writer.write_str(*b) {
+			Ok(_) => { },
+			Err(_) => { ok = false; println!("Failed to write to file {}.", myPath.as_str().unwrap()) }
+		}
+		match writer.flush() {
+			Ok(_) => { },
+			Err(_) => { ok = false; println!("Error writing file {}.", myPath.as_str().unwrap()) }
+		}
+		if ok {
+			written.push(a.trim().to_owned());
+		}
+	}
+	return written;
+}
+
+//Items of a page config value written as "a, b" or ["a", "b"].
+fn configList(value: &str) -> Vec<~str> {
+	let value = value.trim();
+	let items = if value.starts_with("[") && value.ends_with("]") { value.slice(1, value.len() - 1) } else { value };
+	return splitItems(items).iter().map(|item| item.trim_chars(|c: char| c == '"' || c == '\'').to_owned()).collect();
+}
+
+//Redirect stubs for the aliases of pages, the paths they used to have: "aliases: old.html, blog/old/, old-page". Each stub
+//sends visitors and search engines on to the page with a meta refresh and a canonical link. The (alias, path) of each
+//is added to redirects.
+fn aliasPages(pages: &Vec<Page>, vars: &Vec<(~str,~str)>, contentInfo: &Vec<ContentInfo>, hostPath: &str, redirects: &mut Vec<(~str,~str)>) -> Vec<(~str,~str)> {
+	let mut files: Vec<(~str,~str)> = Vec::new();
+	let baseURL = match lookupVar(vars, "baseURL") { Some(url) => url.trim_right_chars('/').to_owned(), None => "".to_owned() };
+	for page in pages.iter() {
+		let aliases = match lookupVar(&page.vars, "aliases") {
+			Some(aliases) => configList(aliases),
+			None => { continue }
+		};
+		let target = format!("{}/{}", hostPath, page.path.trim());
+		for alias in aliases.iter() {
+			let mut aliasPath = alias.trim().trim_left_chars('/').to_owned();
+			//The alias as visitors request it, for redirects files. An alias without an extension is a directory, e.g.
+			//old-page is requested as /old-page/ and its stub is old-page/index.html.
+			let mut from = format!("{}/{}", hostPath, aliasPath);
+			if aliasPath.is_empty() || aliasPath.ends_with("/") {
+				aliasPath.push_str("index.html");
+			}
+			else if !aliasPath.split('/').last().unwrap().contains_char('.') {
+				aliasPath.push_str("/index.html");
+				from.push_char('/');
+			}
+			if !isInsidePath(aliasPath) || aliasPath.contains_char('\\') || aliasPath.contains("://") {
+				warn(format!("The alias {} of {} is outside the output directory. No redirect was made.", *alias, page.sourcePath));
+				continue;
+			}
+			if contentInfo.iter().any(|info| info.path == aliasPath) {
+				warn(format!("The alias {} of {} is the path of another page. No redirect was made.", *alias, page.sourcePath));
+				continue;
+			}
+			if redirects.iter().any(|&(ref other, _)| *other == from) || files.iter().any(|&(ref other, _)| *other == aliasPath) {
+				warn(format!("The alias {} of {} is already an alias of another page.", *alias, page.sourcePath));
+				continue;
+			}
+			//baseURL already ends in hostPath.
+			let url = escapeHTML(if baseURL.is_empty() { target.clone() } else { baseURL + "/" + page.path.trim() });
+			let mut stub = "<!DOCTYPE html>\n<html>\n<head>\n<meta charset='utf-8'>\n".to_owned();
+			stub.push_str("<title>" + escapeText(page.title.trim()) + "</title>\n");
+			stub.push_str("<link rel='canonical' href='" + url + "'>\n");
+			stub.push_str("<meta name='robots' content='noindex'>\n");
+			stub.push_str("<meta http-equiv='refresh' content='0; url=" + url + "'>\n");
+			stub.push_str("</head>\n<body>\n<p>This page has moved to <a href='" + url + "'>" + url + "</a>.</p>\n</body>\n</html>\n");
+			redirects.push((from, target.clone()));
+			files.push((aliasPath, stub));
+		}
+	}
+	return files;
+}
+
+//Redirect files for web hosts, in the formats named in redirects.files: netlify writes _redirects with 301 redirects,
+//nginx writes redirects.map for use in a map block, e.g. map $uri $redirect { include redirects.map; }.
+fn redirectFiles(formats: &Vec<~str>, redirects: &Vec<(~str,~str)>) -> Vec<(~str,~str)> {
+	let mut files: Vec<(~str,~str)> = Vec::new();
+	for format in formats.iter() {
+		let mut text = "".to_owned();
+		for redirect in redirects.iter() {
+			let (ref from, ref to) = *redirect;
+			match format.as_slice() {
+				"netlify" => { text.push_str(*from + " " + *to + " 301\n") },
+				"nginx" => { text.push_str(*from + " " + *to + ";\n") },
+				_ => { }
+			}
+		}
+		match redirectFileName(format.as_slice()) {
+			Some(name) => { files.push((name.to_owned(), text)) },
+			None => { warn(format!("Unknown redirects file format {}. The formats are netlify and nginx.", *format)) }
+		}
+	}
+	return files;
+}
+
+//Name of the redirects file of a format in redirects.files.
+fn redirectFileName(format: &str) -> Option<&'static str> {
+	match format {
+		"netlify" => { return Some("_redirects") },
+		"nginx" => { return Some("redirects.map") },
+		_ => { return None }
+	}
+}
+
+//Leave out alias stubs that would replace another file written to the output directory, and their redirects.
+//staged is every file written there, relative to it. redirects has the redirect of each stub, in the same order.
+fn dropAliasClashes(aliasFiles: Vec<(~str,~str)>, staged: &Vec<~str>, redirects: &mut Vec<(~str,~str)>) -> Vec<(~str,~str)> {
+	let mut files: Vec<(~str,~str)> = Vec::new();
+	let mut kept: Vec<(~str,~str)> = Vec::new();
+	for (file, redirect) in aliasFiles.move_iter().zip(redirects.iter()) {
+		let clash = {
+			let (ref aliasPath, _) = file;
+			staged.contains(aliasPath)
 		};
-		return myPage;
+		if clash {
+			let (ref from, ref target) = *redirect;
+			warn(format!("The alias {} of {} is a file rgen already writes. No redirect was made.", *from, *target));
+			continue;
+		}
+		files.push(file);
+		kept.push(redirect.clone());
 	}
+	*redirects = kept;
+	return files;
 }
 
-struct Block {
-	name: ~str,
-	content: Vec<(~str,~str)> //Vector of tuples: (variable, content)
+//A page in the client-side search index.
+struct SearchEntry {
+	title: ~str,
+	path: ~str, //Absolute path on the site, e.g. /docs/install.html
+	language: ~str,
+	description: ~str,
+	headings: Vec<(~str,~str)>, //(id, text)
+	text: ~str, //Page content without tags or extra whitespace
+	tags: Vec<~str>
 }
 
-impl Clone for Block {
-	fn clone(&self) -> Block {
-		let myBlock = Block { name: self.name.clone(), content: self.content.clone() };
-		return myBlock;
+//Add the pages of one language to the search index. "search: false" in a page's config or vars.txt leaves pages out.
+fn addSearchEntries(pages: &Vec<Page>, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, hostPath: &str, entries: &mut Vec<SearchEntry>) {
+	for page in pages.iter() {
+		if pageSetting(page, vars, "search") == Some("false".to_owned()) {
+			continue;
+		}
+		let html = replaceVars(replacePageVars(page.content.clone(), &page.vars), vars, internalLinks, true);
+		let stripped = stripTags(html);
+		let text: Vec<&str> = stripped.words().collect();
+		let tags: Vec<~str> = match lookupVar(&page.vars, "tags") {
+			Some(tags) => configList(tags),
+			None => Vec::new()
+		};
+		entries.push(SearchEntry {
+			title: unescapeHTML(page.title.trim()),
+			path: format!("{}/{}", hostPath, page.path.trim()),
+			language: page.language.clone(),
+			description: lookupVar(&page.vars, "description").unwrap_or("".to_owned()),
+			headings: page.headings.iter().map(|&(_, ref id, ref text)| (id.clone(), unescapeHTML(*text))).collect(),
+			text: unescapeHTML(text.connect(" ")),
+			tags: tags
+		});
 	}
 }
 
-enum ContentStep {
-	CInConfig,
-	CInCSS,
-	CInJS,
-	CInBlocks,
-	CInContent
+//The text escapeHTML and the Markdown renderer escape, as it was written.
+fn unescapeHTML(text: &str) -> ~str {
+	return text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&#39;", "'").replace("&amp;", "&");
 }
 
-fn loadContent(contentFiles: Vec<Path>, vars: &Vec<(~str,~str)>, internalLinks: &Vec<(~str,~str)>, includes: &Vec<(~str,~str)>) -> Vec<Page> {
-	let mut pages: Vec<Page> = Vec::new();
-	for file in contentFiles.iter() {
-		let mut myPage = Page {path: "".to_owned(), linkName: "".to_owned(), title: "".to_owned(), template: "".to_owned(), blocks: Vec::new(), headData: Vec::new(), content: "".to_owned()};
-		let mut fileReader = BufferedReader::new(File::open(file));
-		let mut curLine = fileReader.read_line().unwrap().trim().to_owned();
-		let mut curBlock = Block { name: "".to_owned(), content: Vec::new() };
-		let mut curBlockPart = "".to_owned();
-		let mut curBlockPartContent = "".to_owned();
-		let mut myStep: ContentStep = CInConfig;
-		match curLine.trim() {
-			"config" => { },
-			_ => { println!("Error loading content. No config found for page at {}", file.as_str()) }
+//Lowercase words of two or more letters, the terms of the inverted search index. search.js splits queries the same way.
+fn searchTerms(text: &str) -> Vec<~str> {
+	let mut terms: Vec<~str> = Vec::new();
+	let mut term = "".to_owned();
+	for c in text.chars().chain(" ".chars()) {
+		if c.is_alphanumeric() {
+			term.push_char(c.to_lowercase());
 		}
-		loop {
-			let nextLine = fileReader.read_line();
-			match nextLine {
-				Ok(tex) => { curLine = tex.to_owned() },
-				Err(_) => { break }
+		else {
+			if term.char_len() > 1 {
+				terms.push(term.clone());
 			}
-			let mut advanced = false;
-			match curLine.trim() {
-				"css" => {
-					myStep = CInCSS;
-					advanced = true;
-				},
-				"js" => {
-					myStep = CInJS;
-					advanced = true;
-				},
-				"blocks" => {
-					myStep = CInBlocks;
-					advanced = true;
-				},
-				"" => {
-					advanced = true; //Either we advance or this line is blank so ignore it regardless
-					match myStep {
-						CInBlocks => { myStep = CInContent; advanced = true; },
-						_ => { }
-					}
-				},
-				_ => { }
+			term = "".to_owned();
+		}
+	}
+	return terms;
+}
+
+//JSON of the search index: {"pages": [...]} with the text of every page, or when inverted the pages without their
+//text and {"index": {"term": [[page, count], ...]}} with the number of times each term is in each page.
+fn searchIndex(entries: &Vec<SearchEntry>, inverted: bool) -> ~str {
+	let mut pages: Vec<~str> = Vec::new();
+	//(term, page) for every term in every page.
+	let mut occurrences: Vec<(~str, uint)> = Vec::new();
+	for (i, entry) in entries.iter().enumerate() {
+		let headings: Vec<~str> = entry.headings.iter().map(|&(ref id, ref text)| "{\"id\": " + jsonString(*id) + ", \"text\": " + jsonString(*text) + "}").collect();
+		let mut page = "\n\t\t{\"title\": " + jsonString(entry.title) + ", \"path\": " + jsonString(entry.path);
+		page.push_str(", \"language\": " + jsonString(entry.language) + ", \"description\": " + jsonString(entry.description));
+		page.push_str(", \"headings\": [" + headings.connect(", ") + "], \"tags\": " + jsonList(&entry.tags));
+		if inverted {
+			let mut words = entry.title + " " + entry.description + " " + entry.text + " " + entry.tags.connect(" ");
+			for &(_, ref text) in entry.headings.iter() {
+				words.push_str(" " + *text);
 			}
-			if !advanced {
-				let curLineUnTrimmed = curLine.to_owned();
-				curLine = curLine.trim().to_owned();
-				//Replace variables/links/includes if we might need to.
-				if curLine.contains_char('{') && curLine.contains_char('}') {
-					//Check if there are variables/links and replace them.
-					if curLine.contains("{$") || curLine.contains("{%") {
-						curLine = replaceVars(curLine, vars, internalLinks);
-					}
-					//Check if there are includes and insert them.
-					if curLine.contains("{.") {
-						curLine = insertIncludes(curLine, includes);
-					}
-				}
-				match myStep {
-					CInConfig => {
-						let mut splitString = curLine.split_str(":");
-						match splitString.next().unwrap() {
-							"path" => {
-								myPage.path = splitString.last().unwrap().to_owned();
-							},
-							"linkName" => {
-								myPage.linkName = splitString.last().unwrap().to_owned();
-							},
-							"title" => {
-								myPage.title = splitString.last().unwrap().to_owned();
-							},
-							"template" => {
-								myPage.template = splitString.last().unwrap().to_owned();
-							}
-							_ => { }
-						}
-					}, 
-					CInCSS => {
-						myPage.headData.push("<link rel='stylesheet' type='text/css' href='resources/css/" + curLine + "'>");
-					},
-					CInJS => {
-						myPage.headData.push("<script type='text/javascript' src='resources/js/" + curLine + "'></script>");
-					},
-					CInBlocks => {
-						//Support double tab or 8 spaces. This is not very flexible. 
-						if curLineUnTrimmed.starts_with("\t\t\t") || curLineUnTrimmed.starts_with("            ") {
-							//In a part
-							curBlockPartContent = curBlockPartContent + "\n" + curLine;
-						}
-						else if curLineUnTrimmed.starts_with("\t\t") || curLineUnTrimmed.starts_with("        ") {
-							//Found a new part
-							if curBlockPart != "".to_owned() && curBlockPartContent != "".to_owned() {
-								curBlock.content.push((curBlockPart.to_owned(), curBlockPartContent.to_owned()));
-							}
-							else if curBlockPart != "".to_owned() {
-								//If the current block implements the default part. 
-								//This restricts default parts to being a single line. If you want more then name it.
-								curBlock.content.push((curBlockPartContent.to_owned(), curBlockPart.to_owned()));
-							}
-							else {
-								curBlockPart = curLine;
-							}
-						}
-						else {
-							//Found a new block
-							let curBlockClone = curBlock.clone();
-							myPage.blocks.push(curBlockClone);
-							curBlock = Block { name: "".to_owned(), content: Vec::new() };
-							curBlockPart = "".to_owned();
-							curBlockPartContent = "".to_owned();
-						}
-					},
-					CInContent => {
-						myPage.content = curLine;
-						match fileReader.read_to_str() {
-							Ok(tex) => {
-								myPage.content = myPage.content + "\n" + tex;
-							},
-							Err(_) => {}
-						}
-					}
-				}
+			for term in searchTerms(words).move_iter() {
+				occurrences.push((term, i));
 			}
 		}
-		pages.push(myPage);
+		else {
+			page.push_str(", \"text\": " + jsonString(entry.text));
+		}
+		page.push_str("}");
+		pages.push(page);
 	}
-	return pages;
+	let mut text = "{\n\t\"pages\": [" + pages.connect(",") + "\n\t]";
+	if inverted {
+		occurrences.sort_by(|a, b| a.cmp(b));
+		let mut terms: Vec<~str> = Vec::new();
+		let mut postings: Vec<~str> = Vec::new();
+		let mut count = 0;
+		for (i, occurrence) in occurrences.iter().enumerate() {
+			let (ref term, page) = *occurrence;
+			count += 1;
+			let last = i + 1 == occurrences.len();
+			let nextTerm = if last { None } else { let (ref t, p) = *occurrences.get(i + 1); Some((t.clone(), p)) };
+			//The end of this term's occurrences in this page.
+			if nextTerm.as_ref().map_or(true, |&(ref t, p)| t != term || p != page) {
+				postings.push(format!("[{}, {}]", page, count));
+				count = 0;
+			}
+			if nextTerm.as_ref().map_or(true, |&(ref t, _)| t != term) {
+				terms.push("\n\t\t" + jsonString(*term) + ": [" + postings.connect(", ") + "]");
+				postings = Vec::new();
+			}
+		}
+		text.push_str(",\n\t\"index\": {" + terms.connect(",") + "\n\t}");
+	}
+	text.push_str("\n}\n");
+	return text;
 }
 
-fn mdToHTML(pages: &mut Vec<Page>) {
-	//Turn Markdown into HTML
-
+//Copy resources to /output/resources, keeping their directories. Returns their paths relative to /output.
+fn copyResources(resourceFiles: &Vec<Path>, pathToResources: &Path, pathToOutput: &Path) -> Vec<~str> {
+	let mut copied: Vec<~str> = Vec::new();
+	for p in resourceFiles.iter() {
+		let rel = match p.path_relative_from(pathToResources) {
+			Some(rel) => "resources/" + rel.as_str().unwrap(),
+			None => { continue }
+		};
+		let target = pathToOutput.join(rel.as_slice());
+		match fs::mkdir_recursive(&target.dir_path(), io::UserRWX) {
+			Ok(_) => { },
+			Err(_) => { }
+		}
+		match fs::copy(p, &target) {
+			Ok(_) => { copied.push(rel) },
+			Err(_) => { println!("Error: Could not copy {} to {}.", p.as_str().unwrap(), target.as_str().unwrap()) }
+		}
+	}
+	return copied;
 }
 
-/*
-struct Page {
-	path: ~str,
-	linkName: ~str,
-	title: ~str,
-	template: ~str,
-	blocks: Vec<Block>,
-	headData: Vec<~str>,
-	content: ~str
+//Whether a relative path stays inside the directory it is relative to: it isn't absolute and has no .. in it.
+fn isInsidePath(rel: &str) -> bool {
+	return !rel.is_empty() && !rel.starts_with("/") && !rel.split('/').any(|part| part == "..");
 }
 
-struct Block {
-	name: ~str,
-	content: Vec<(~str,~str)> //Vector of tuples: (variable, content)
+//The site directory or source directory that building into pathToOutput would move or delete, if any: the site
+//directory if the output is it or contains it, a source directory if the output is it, contains it or is inside it.
+fn outputClash(pathToOutput: &Path, sitePath: &Path, sourceDirs: &Vec<Path>) -> Option<Path> {
+	let output = os::make_absolute(pathToOutput);
+	let site = os::make_absolute(sitePath);
+	if output == site || output.is_ancestor_of(&site) {
+		return Some(sitePath.clone());
+	}
+	for dir in sourceDirs.iter() {
+		let source = os::make_absolute(dir);
+		if output == source || output.is_ancestor_of(&source) || source.is_ancestor_of(&output) {
+			return Some(dir.clone());
+		}
+	}
+	return None;
 }
 
-struct Template {
-	name: ~str,
-	inherit: ~str,
-	headData: Vec<~str>,
-	blockTemplates: Vec<(~str,~str)>,
-	content: ~str
-}*/
+fn isProtected(rel: &str, protectedFiles: &Vec<~str>) -> bool {
+	for protected in protectedFiles.iter() {
+		let protected = protected.trim_chars('/');
+		if rel == protected || rel.starts_with(protected + "/") {
+			return true;
+		}
+	}
+	return false;
+}
 
-fn processContent(pages: Vec<Page>, templates: Vec<Template>, resourceNames: Vec<(~str,~str)>, globalCSSJS: Vec<~str>) -> Vec<(~str,~str)> {
-	let mut returnVec: Vec<(~str,~str)> = Vec::new();
-	for page in pages.iter() {
-		let pageURL = page.path.to_owned();
-		let mut pageContent = "".to_owned();
-		for template in templates.iter() {
-			if template.name.trim() == page.template.trim() {
-				pageContent = template.content.replace("{content}", page.content);
-				let headDataVec = page.headData.clone().append(template.headData.as_slice());
-				let mut headDataStr = "".to_owned();
-				for line in headDataVec.iter() {
-					headDataStr = headDataStr + "\n" + line.to_owned();
+//Files of page bundles. A bundle is a directory below /content with an index content file, e.g. blog/trip/index.md,
+//and the files beside it, such as photos. Files with the index file's extension and other files that start with
+//config are pages like any other, the rest belong to the nearest bundle above them. Returns (file, bundle directory).
+fn bundleAssets(files: &Vec<Path>, pathToContent: &Path) -> Vec<(Path, Path)> {
+	//(directory, extension of its index file)
+	let mut bundles: Vec<(Path, ~str)> = Vec::new();
+	for p in files.iter() {
+		let dir = p.dir_path();
+		if p.filename_str().unwrap().starts_with("index.") && dir != *pathToContent && !bundles.iter().any(|&(ref d, _)| *d == dir) {
+			bundles.push((dir, p.extension_str().unwrap_or("").to_owned()));
+		}
+	}
+	let mut assets: Vec<(Path, Path)> = Vec::new();
+	for p in files.iter() {
+		//The nearest bundle is the one with the longest path.
+		let mut nearest: Option<&(Path, ~str)> = None;
+		for bundle in bundles.iter() {
+			let (ref dir, _) = *bundle;
+			if dir.is_ancestor_of(p) && nearest.map_or(true, |&(ref n, _)| dir.as_vec().len() > n.as_vec().len()) {
+				nearest = Some(bundle);
+			}
+		}
+		match nearest {
+			Some(&(ref dir, ref extension)) => {
+				if p.extension_str().unwrap_or("") != extension.as_slice() && !isContentFile(p) {
+					assets.push((p.clone(), dir.clone()));
 				}
-				pageContent = pageContent.replace("<head>", "<head>" + headDataStr);
-				for blockTemplate in template.blockTemplates.iter() {
-					let (ref blockTempName, ref blockTempCont) = *blockTemplate;
-					let mut myBlocks: Vec<~str> = Vec::new();
-					for block in page.blocks.iter() {
-						if block.name == *blockTempName {
-							let mut blockContent = blockTempCont.to_owned();
-							for contentBlock in block.content.iter() {
-								let (ref a, ref b) = *contentBlock;
-								blockContent = blockContent.replace("{" + a.trim() + "}", *b);
-							}
-							myBlocks.push(blockContent);
-						}
-					}
-					let mut myBlocksStr = "".to_owned();
-					for string in myBlocks.iter() {
-						myBlocksStr = myBlocksStr + *string;
+			},
+			None => { }
+		}
+	}
+	return assets;
+}
+
+//Whether a file is written like a content file: its first line that isn't blank is config.
+fn isContentFile(p: &Path) -> bool {
+	let mut file = match File::open(p) {
+		Ok(file) => file,
+		Err(_) => { return false }
+	};
+	let mut buffer = [0u8, ..256];
+	let length = match file.read(buffer) {
+		Ok(length) => length,
+		Err(_) => { return false }
+	};
+	let start = buffer.slice_to(length);
+	let mut i = 0;
+	while i < start.len() && (start[i] as char).is_whitespace() {
+		i += 1;
+	}
+	let rest = start.slice_from(i);
+	return rest.starts_with(bytes!("config")) && (rest.len() == "config".len() || (rest["config".len()] as char).is_whitespace());
+}
+
+//Copy the files of each page bundle into the output directory of every published page in it, so that the page can
+//link to them relative to itself, e.g. <img src='photo.jpg'>. Returns the paths copied relative to /output and the
+//number of files that couldn't be copied. Files of different bundles that would be copied to the same path, e.g. the
+//photo.jpg of two bundles whose pages are in the same directory, are an error.
+fn copyBundleFiles(bundleFiles: &Vec<(Path, Path)>, contentInfo: &Vec<ContentInfo>, pathToOutput: &Path) -> (Vec<~str>, uint) {
+	let mut copied: Vec<~str> = Vec::new();
+	//The file each path in copied was copied from.
+	let mut copiedFrom: Vec<Path> = Vec::new();
+	let mut failures = 0;
+	for info in contentInfo.iter() {
+		if !info.published || info.path.is_empty() || !info.file.filename_str().unwrap().starts_with("index.") {
+			continue;
+		}
+		let bundleDir = info.file.dir_path();
+		let outputDir = Path::new(info.path.as_slice()).dir_path();
+		for &(ref p, ref dir) in bundleFiles.iter() {
+			if *dir != bundleDir {
+				continue;
+			}
+			let target = pathToOutput.join(&outputDir).join(&p.path_relative_from(dir).unwrap());
+			let rel = target.path_relative_from(pathToOutput).unwrap().as_str().unwrap().to_owned();
+			match copied.iter().position(|other| *other == rel) {
+				Some(i) => {
+					//Translations of a page can share an output directory and their bundle's files.
+					if copiedFrom.get(i) != p {
+						reportError(format!("{} and {} would both be copied to {}. Rename one of them or move its page to a directory of its own.", copiedFrom.get(i).as_str().unwrap(), p.as_str().unwrap(), rel));
 					}
-					pageContent = pageContent.replace("{" + blockTempName.trim() + "}", myBlocksStr);
+					continue;
+				},
+				None => { }
+			}
+			match fs::mkdir_recursive(&target.dir_path(), io::UserRWX) {
+				Ok(_) => { },
+				Err(_) => { }
+			}
+			match fs::copy(p, &target) {
+				Ok(_) => {
+					copied.push(rel);
+					copiedFrom.push(p.clone());
+				},
+				Err(_) => {
+					println!("Error: Could not copy {} to {}.", p.as_str().unwrap(), target.as_str().unwrap());
+					failures += 1;
 				}
 			}
 		}
-		let mut globalCSSJSStr = "".to_owned();
-		for cssJsLine in globalCSSJS.iter() {
-			globalCSSJSStr = globalCSSJSStr + "\n" + *cssJsLine;
+	}
+	return (copied, failures);
+}
+
+//Put back the last complete build if a build stopped while swapping it for a new one and left only output.rgen-old.
+//An output.rgen-old next to an output directory is left over from after the swap and is removed.
+fn recoverOutput(pathToOutput: &Path) -> bool {
+	let pathToOld = siblingPath(pathToOutput, "old");
+	if !pathToOld.exists() {
+		return true;
+	}
+	if pathToOutput.exists() {
+		match fs::rmdir_recursive(&pathToOld) {
+			Ok(_) => { },
+			Err(_) => { warn(format!("Could not remove {}.", pathToOld.as_str().unwrap())) }
 		}
-		pageContent = pageContent.replace("<head>", "<head>\n<title>" + page.title.trim() + "</title>\n" + globalCSSJSStr);
-		//replace resource names
-		for resource in resourceNames.iter() {
-			//(~str,~str) (filename, path)
-			let (ref a, ref b) = *resource;
-			pageContent = pageContent.replace("{$" + a.trim() + "}", *b);
+		return true;
+	}
+	println!("A build stopped while publishing. Putting back the last build from {}.", pathToOld.as_str().unwrap());
+	match fs::rename(&pathToOld, pathToOutput) {
+		Ok(_) => { return true },
+		Err(_) => {
+			println!("Error: Could not move {} back to {}.", pathToOld.as_str().unwrap(), pathToOutput.as_str().unwrap());
+			return false;
 		}
-		returnVec.push((pageURL, pageContent));
 	}
-	return returnVec;
 }
 
-fn outputFiles(files: Vec<(~str,~str)>, path: Path) {
-	for file in files.iter() {
-		let (ref a, ref b) = *file;
-		println!("({}, {})", *a, *b);
-		match fs::mkdir(&path, io::UserRWX) {
-			Ok(_) => { },
-			Err(_) => { }
+//A directory next to path, e.g. output.rgen-staging for output.
+fn siblingPath(path: &Path, suffix: &str) -> Path {
+	return path.dir_path().join(path.filename_str().unwrap() + ".rgen-" + suffix);
+}
+
+//Put the finished build in pathToStaging in place of pathToOutput, together with the output files rgen didn't generate
+//(everything not in the last build's manifest) and protected files. With clean only protected files are kept.
+//Generated files that the new build doesn't have are left behind. Returns false if the output was left as it was.
+//The swap is two renames: the output to output.rgen-old, then the staging directory to the output. If rgen stops
+//between them there is no output directory, only output.rgen-old, which recoverOutput puts back on the next run.
+fn publishStaging(pathToStaging: &Path, pathToOutput: &Path, generated: &Vec<~str>, protectedFiles: &Vec<~str>, clean: bool) -> bool {
+	let mut manifest = "".to_owned();
+	for rel in generated.iter() {
+		manifest = manifest + *rel + "\n";
+	}
+	if !writeNewFile(&pathToStaging.join(MANIFEST), manifest) {
+		return false;
+	}
+	let pathToOld = siblingPath(pathToOutput, "old");
+	if pathToOutput.exists() {
+		let mut lastGenerated: Vec<~str> = Vec::new();
+		let manifestPath = pathToOutput.join(MANIFEST);
+		if manifestPath.exists() {
+			let mut fileReader = BufferedReader::new(File::open(&manifestPath));
+			for line in fileReader.lines() {
+				//Only paths inside the output directory, in case the manifest was edited.
+				let rel = line.unwrap().trim().to_owned();
+				if isInsidePath(rel) {
+					lastGenerated.push(rel);
+				}
+			}
 		}
-		let myPath = Path::new(path.as_str().unwrap() + "/" + *a);
-		let mut writer = BufferedWriter::new(File::create(&myPath));
-		match writer.write_str(*b) {
+		let entries: Vec<Path> = fs::walk_dir(pathToOutput).ok().unwrap().collect();
+		for p in entries.iter() {
+			let rel = p.path_relative_from(pathToOutput).unwrap().as_str().unwrap().to_owned();
+			//A report an earlier build wrote here isn't kept, since reports aren't part of the site.
+			if p.is_dir() || rel.as_slice() == MANIFEST || rel.as_slice() == REPORT || generated.contains(&rel) {
+				continue;
+			}
+			if isProtected(rel, protectedFiles) || (!clean && !lastGenerated.contains(&rel)) {
+				let target = pathToStaging.join(rel.as_slice());
+				match fs::mkdir_recursive(&target.dir_path(), io::UserRWX) {
+					Ok(_) => { },
+					Err(_) => { }
+				}
+				match fs::copy(p, &target) {
+					Ok(_) => { },
+					Err(_) => {
+						println!("Error: Could not keep {}. {} was left as it was.", rel, pathToOutput.as_str().unwrap());
+						return false;
+					}
+				}
+			}
+			else if lastGenerated.contains(&rel) {
+				println!("Removing stale file {}", rel);
+			}
+		}
+		if pathToOld.exists() {
+			match fs::rmdir_recursive(&pathToOld) {
+				Ok(_) => { },
+				Err(_) => { }
+			}
+		}
+		match fs::rename(pathToOutput, &pathToOld) {
 			Ok(_) => { },
-			Err(_) => { println!("Failed to write to file {}.", path.as_str().unwrap()) }
+			Err(_) => {
+				println!("Error: Could not move {} out of the way. It was left as it was.", pathToOutput.as_str().unwrap());
+				return false;
+			}
 		}
-		match writer.flush() {
+	}
+	match fs::rename(pathToStaging, pathToOutput) {
+		Ok(_) => { },
+		Err(_) => {
+			println!("Error: Could not move the new build to {}.", pathToOutput.as_str().unwrap());
+			//Put the old build back.
+			if pathToOld.exists() {
+				match fs::rename(&pathToOld, pathToOutput) {
+					Ok(_) => { },
+					Err(_) => { println!("Error: The last build is in {}.", pathToOld.as_str().unwrap()) }
+				}
+			}
+			return false;
+		}
+	}
+	if pathToOld.exists() {
+		match fs::rmdir_recursive(&pathToOld) {
 			Ok(_) => { },
-			Err(_) => { println!("Error writing file {}.", path.as_str().unwrap()) }
+			Err(_) => { warn(format!("Could not remove {}.", pathToOld.as_str().unwrap())) }
+		}
+	}
+	return true;
+}
+
+//Name of the file next to the output directory that --report json writes.
+static REPORT: &'static str = "rgen-report.json";
+
+//Write the report of a build that stopped before building anything, with its errors and warnings, and fail.
+fn writeFailureReport(reportPath: &Option<Path>) {
+	os::set_exit_status(1);
+	match *reportPath {
+		Some(ref reportPath) => {
+			let errors = match ERRORS.replace(None) {
+				Some(errors) => errors,
+				None => Vec::new()
+			};
+			let warnings = match WARNINGS.replace(None) {
+				Some(warnings) => warnings,
+				None => Vec::new()
+			};
+			if writeReport(reportPath, false, &Vec::new(), &Vec::new(), &Vec::new(), &errors, &warnings, &Vec::new()) {
+				println!("Build report written to {}.", reportPath.as_str().unwrap());
+			}
+			else {
+				println!("Error: Could not write {}.", reportPath.as_str().unwrap());
+			}
+		},
+		None => { }
+	}
+}
+
+//A generated page, for the build report.
+struct PageReport {
+	source: ~str, //Content file relative to /content
+	templates: Vec<~str>, //The page's template and the templates it inherits from
+	output: ~str, //Path relative to /output
+	size: uint //Bytes
+}
+
+//Add the time since stageStart to a stage of the build and start the next one.
+fn addTiming(timings: &mut Vec<(~str, u64)>, stage: &str, stageStart: &mut u64) {
+	let now = time::precise_time_ns();
+	let elapsed = now - *stageStart;
+	*stageStart = now;
+	for timing in timings.mut_iter() {
+		let (ref a, ref mut b) = *timing;
+		if a.as_slice() == stage {
+			*b += elapsed;
+			return;
+		}
+	}
+	timings.push((stage.to_owned(), elapsed));
+}
+
+fn jsonString(text: &str) -> ~str {
+	return json::String(text.to_owned()).to_str();
+}
+
+fn jsonList(items: &Vec<~str>) -> ~str {
+	let strings: Vec<~str> = items.iter().map(|item| jsonString(*item)).collect();
+	return "[" + strings.connect(", ") + "]";
+}
+
+//Write a JSON report of the build for CI and scripts. Timings are in milliseconds.
+fn writeReport(reportPath: &Path, success: bool, pageReports: &Vec<PageReport>, resources: &Vec<~str>, excluded: &Vec<(~str,~str)>, errors: &Vec<~str>, warnings: &Vec<~str>, timings: &Vec<(~str, u64)>) -> bool {
+	let mut pages: Vec<~str> = Vec::new();
+	for page in pageReports.iter() {
+		pages.push("\n\t\t{\"source\": " + jsonString(page.source) + ", \"templates\": " + jsonList(&page.templates) + ", \"output\": " + jsonString(page.output) + ", \"size\": " + page.size.to_str() + "}");
+	}
+	let mut excludedPages: Vec<~str> = Vec::new();
+	for page in excluded.iter() {
+		let (ref a, ref b) = *page;
+		excludedPages.push("\n\t\t{\"source\": " + jsonString(*a) + ", \"reason\": " + jsonString(*b) + "}");
+	}
+	let mut stages: Vec<~str> = Vec::new();
+	for timing in timings.iter() {
+		let (ref a, ref b) = *timing;
+		stages.push("\n\t\t" + jsonString(*a) + ": " + format!("{:.3f}", *b as f64 / 1000000.0));
+	}
+	let mut text = "{\n".to_owned();
+	text.push_str("\t\"success\": " + success.to_str() + ",\n");
+	text.push_str("\t\"pages\": [" + pages.connect(",") + "\n\t],\n");
+	text.push_str("\t\"resources\": " + jsonList(resources) + ",\n");
+	text.push_str("\t\"excluded\": [" + excludedPages.connect(",") + "\n\t],\n");
+	text.push_str("\t\"errors\": " + jsonList(errors) + ",\n");
+	text.push_str("\t\"warnings\": " + jsonList(warnings) + ",\n");
+	text.push_str("\t\"timings\": {" + stages.connect(",") + "\n\t}\n");
+	text.push_str("}\n");
+	//A build that failed before publishing may not have an output directory yet.
+	match fs::mkdir_recursive(&reportPath.dir_path(), io::UserRWX) {
+		Ok(_) => { },
+		Err(_) => { }
+	}
+	let mut writer = BufferedWriter::new(File::create(reportPath));
+	match writer.write_str(text) {
+		Ok(_) => { },
+		Err(_) => { return false }
+	}
+	match writer.flush() {
+		Ok(_) => { return true },
+		Err(_) => { return false }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{expandConditionals, parseContentFile, parseTemplateFile, sectionItems, SourceFile};
+
+	static CONTENT: &'static str = "config\n\tpath: a.html\n\ttitle: A\nblocks\n\tfeature\n\t\ttitle\n\t\t\tFast\n\t\tbody\n\t\t\tPlain Markdown\n\tnote\n\t\tHello\n\nBody text\n";
+
+	//The fixture with each tab replaced by indent.
+	fn indented(text: &str, indent: &str) -> ~str {
+		return text.replace("\t", indent);
+	}
+
+	fn parsed(text: &str) -> SourceFile {
+		match parseContentFile(text) {
+			Ok(source) => source,
+			Err(e) => fail!("{}:{}: {}", e.span.line, e.span.column, e.message)
+		}
+	}
+
+	fn checkContent(source: &SourceFile) {
+		let names: Vec<~str> = source.sections.iter().map(|section| section.name.clone()).collect();
+		assert_eq!(names, vec!("config".to_owned(), "blocks".to_owned()));
+		let config: Vec<~str> = sectionItems(source, "config").iter().map(|item| item.text.clone()).collect();
+		assert_eq!(config, vec!("path: a.html".to_owned(), "title: A".to_owned()));
+		let blocks = sectionItems(source, "blocks");
+		let blockNames: Vec<~str> = blocks.iter().map(|block| block.text.clone()).collect();
+		assert_eq!(blockNames, vec!("feature".to_owned(), "note".to_owned()));
+		let feature = blocks.get(0);
+		assert_eq!(feature.children.len(), 2);
+		assert_eq!(feature.children.get(0).text, "title".to_owned());
+		assert_eq!(feature.children.get(0).children.get(0).text, "Fast".to_owned());
+		assert_eq!(feature.children.get(1).text, "body".to_owned());
+		assert_eq!(feature.children.get(1).children.get(0).text, "Plain Markdown".to_owned());
+		assert_eq!(blocks.get(1).children.get(0).text, "Hello".to_owned());
+		assert_eq!(source.body, "Body text\n".to_owned());
+	}
+
+	#[test]
+	fn tabIndentation() {
+		checkContent(&parsed(CONTENT));
+	}
+
+	#[test]
+	fn twoSpaceIndentation() {
+		checkContent(&parsed(indented(CONTENT, "  ")));
+	}
+
+	#[test]
+	fn fourSpaceIndentation() {
+		checkContent(&parsed(indented(CONTENT, "    ")));
+	}
+
+	#[test]
+	fn spansOfItems() {
+		let source = parsed(indented(CONTENT, "  "));
+		let blocks = sectionItems(&source, "blocks");
+		assert_eq!((blocks.get(1).span.line, blocks.get(1).span.column), (10, 3));
+		let part = blocks.get(0).children.get(1);
+		assert_eq!((part.span.line, part.span.column), (8, 5));
+	}
+
+	#[test]
+	fn lastBlockRunsToEndOfFile() {
+		let source = parsed("config\n\tpath: a.html\nblocks\n\tfeature\n\t\tbody\n\t\t\tFirst line\n\t\t\tLast line");
+		let blocks = sectionItems(&source, "blocks");
+		assert_eq!(blocks.len(), 1);
+		assert_eq!(blocks.get(0).text, "feature".to_owned());
+		let lines: Vec<~str> = blocks.get(0).children.get(0).children.iter().map(|line| line.text.clone()).collect();
+		assert_eq!(lines, vec!("First line".to_owned(), "Last line".to_owned()));
+		assert_eq!(source.body, "".to_owned());
+	}
+
+	#[test]
+	fn mismatchedIndentation() {
+		match parseContentFile("config\n\tpath: a.html\n  title: A\n") {
+			Ok(_) => fail!("expected an error"),
+			Err(e) => {
+				assert_eq!((e.span.line, e.span.column), (3, 3));
+				assert_eq!(e.message, "Indentation doesn't match line 2.".to_owned());
+			}
+		}
+		match parseContentFile("config\n\tpath: a.html\nblocks\n\tfeature\n\t\ttitle\n\t\t\tFast\n\t   body\n") {
+			Ok(_) => fail!("expected an error"),
+			Err(e) => {
+				assert_eq!((e.span.line, e.span.column), (7, 5));
+				assert_eq!(e.message, "Indentation doesn't match line 5.".to_owned());
+			}
+		}
+	}
+
+	#[test]
+	fn contentStartsWithConfig() {
+		match parseContentFile("blocks\n\tfeature\n") {
+			Ok(_) => fail!("expected an error"),
+			Err(e) => { assert_eq!((e.span.line, e.span.column), (1, 1)) }
 		}
 	}
+
+	#[test]
+	fn templateBlocks() {
+		let source = match parseTemplateFile("inherit base\nblocks\n  note\n    <aside class='note'>\n      {text}\n    </aside>\n\n<main>\n{content}\n</main>\n") {
+			Ok(source) => source,
+			Err(e) => fail!("{}:{}: {}", e.span.line, e.span.column, e.message)
+		};
+		assert_eq!(source.sections.get(0).argument, "base".to_owned());
+		let blocks = sectionItems(&source, "blocks");
+		assert_eq!(blocks.get(0).text, "note".to_owned());
+		//HTML keeps its indentation beyond that of its first line.
+		let html: Vec<~str> = blocks.get(0).children.iter().map(|line| line.text.clone()).collect();
+		assert_eq!(html, vec!("<aside class='note'>".to_owned(), "  {text}".to_owned(), "</aside>".to_owned()));
+		assert_eq!(source.body, "<main>\n{content}\n</main>\n".to_owned());
+	}
+
+	#[test]
+	fn templateWithoutSections() {
+		let source = match parseTemplateFile("<!DOCTYPE html>\n<html>\n") {
+			Ok(source) => source,
+			Err(e) => fail!("{}:{}: {}", e.span.line, e.span.column, e.message)
+		};
+		assert_eq!(source.sections.len(), 0);
+		assert_eq!(source.body, "<!DOCTYPE html>\n<html>\n".to_owned());
+	}
+
+	#[test]
+	fn elseAfterNestedCondition() {
+		let text = "{#if a}{#if b}B{/if}A{#else}not A{/if}".to_owned();
+		assert_eq!(expandConditionals(text.clone(), |name| Some(name == "a"), true), "A".to_owned());
+		assert_eq!(expandConditionals(text.clone(), |_| Some(false), true), "not A".to_owned());
+		assert_eq!(expandConditionals("{#if a}{#if b}B{#else}not B{/if}{#else}not A{/if}".to_owned(), |name| Some(name == "a"), true), "not B".to_owned());
+	}
 }